@@ -101,7 +101,8 @@ pub async fn setup_services(config: &Option<PathBuf>, container_mode: bool) -> a
                 "api_key": "${ES_API_KEY:}",
                 "username": "${ES_USERNAME:}",
                 "password": "${ES_PASSWORD:}",
-                "ssl_skip_verify": "${ES_SSL_SKIP_VERIFY:false}"
+                "ssl_skip_verify": "${ES_SSL_SKIP_VERIFY:false}",
+                "admin_tools": "${ES_ADMIN_TOOLS:false}"
             }
         }"#
         .to_string()
@@ -117,7 +117,12 @@ impl HttpProtocol {
 
         // Start the http server
         let listener = tokio::net::TcpListener::bind(config.bind).await?;
-        let server = axum::serve(listener, main_router).with_graceful_shutdown({
+        // Connection info is used to identify callers by their IP address
+        let server = axum::serve(
+            listener,
+            main_router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown({
             let ct = ct.clone();
             async move {
                 ct.cancelled().await;
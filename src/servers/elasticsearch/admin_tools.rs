@@ -0,0 +1,915 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The `admin` tool group: index, mapping, template, alias and ILM management, and task cancellation.
+//!
+//! These tools are only registered if `admin_tools` is enabled in the configuration. Every tool
+//! first validates its input against the current state of the cluster and returns a preview of
+//! the changes. Changes are only applied if the tool is called with `apply: true`.
+
+use crate::servers::elasticsearch::audit;
+use crate::servers::elasticsearch::base_tools::EsBaseTools;
+use crate::servers::elasticsearch::cluster_tools::TaskInfo;
+use crate::servers::elasticsearch::{handle_error, read_json, read_json_opt};
+use crate::utils::json_diff;
+use elasticsearch::cluster::{ClusterGetComponentTemplateParts, ClusterPutComponentTemplateParts};
+use elasticsearch::ilm::{IlmGetLifecycleParts, IlmPutLifecycleParts};
+use elasticsearch::indices::{
+    IndicesCreateParts, IndicesExistsParts, IndicesGetAliasParts, IndicesGetIndexTemplateParts, IndicesGetMappingParts,
+    IndicesGetSettingsParts, IndicesPutIndexTemplateParts, IndicesPutMappingParts, IndicesPutSettingsParts,
+    IndicesRolloverParts,
+};
+use elasticsearch::tasks::{TasksCancelParts, TasksGetParts};
+use http::StatusCode;
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde_json::{Map, Value, json};
+
+/// Static settings, that can't be changed on an existing index. Names ending with `.*` are prefixes.
+const STATIC_SETTINGS: &[&str] = &[
+    "index.number_of_shards",
+    "index.number_of_routing_shards",
+    "index.routing_partition_size",
+    "index.routing_path",
+    "index.mode",
+    "index.codec",
+    "index.store.type",
+    "index.soft_deletes.enabled",
+    "index.shard.check_on_startup",
+    "index.load_fixed_bitset_filters_eagerly",
+    "index.mapping.source.mode",
+    "index.sort.*",
+];
+
+/// Is this a static index setting?
+fn is_static_setting(name: &str) -> bool {
+    STATIC_SETTINGS.iter().any(|setting| match setting.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == *setting,
+    })
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct CreateIndexParams {
+    /// Name of the index to create
+    index: String,
+
+    /// Index settings (e.g. `{"number_of_shards": 1, "number_of_replicas": 1}`)
+    settings: Option<Map<String, Value>>,
+
+    /// Index mappings (e.g. `{"properties": {"field": {"type": "keyword"}}}`)
+    mappings: Option<Map<String, Value>>,
+
+    /// Index aliases (e.g. `{"my-alias": {}}`)
+    aliases: Option<Map<String, Value>>,
+
+    /// Apply the change. If false or missing, only a preview of the change is returned
+    apply: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct PutMappingParams {
+    /// Name or pattern of the indices to update
+    index: String,
+
+    /// Field definitions to add or update (e.g. `{"field": {"type": "keyword"}}`)
+    properties: Map<String, Value>,
+
+    /// Apply the change. If false or missing, only a preview of the change is returned
+    apply: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct UpdateSettingsParams {
+    /// Name or pattern of the indices to update
+    index: String,
+
+    /// Dynamic index settings to update (e.g. `{"number_of_replicas": 2}`)
+    settings: Map<String, Value>,
+
+    /// Apply the change. If false or missing, only a preview of the change is returned
+    apply: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct PutTemplateParams {
+    /// Name of the template
+    name: String,
+
+    /// Complete template definition, as expected by the Elasticsearch API
+    template: Map<String, Value>,
+
+    /// Apply the change. If false or missing, only a preview of the change is returned
+    apply: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum AliasActionType {
+    Add,
+    Remove,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct AliasAction {
+    /// Action to perform
+    action: AliasActionType,
+
+    /// Concrete index name
+    index: String,
+
+    /// Alias name
+    alias: String,
+
+    /// Make this index the write index of the alias (add only)
+    is_write_index: Option<bool>,
+
+    /// Query DSL filter for a filtered alias (add only)
+    filter: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ManageAliasParams {
+    /// Alias actions, applied atomically
+    actions: Vec<AliasAction>,
+
+    /// Apply the change. If false or missing, only a preview of the change is returned
+    apply: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct RolloverParams {
+    /// Name of the alias or data stream to roll over
+    alias: String,
+
+    /// Name of the new index (optional, generated by default)
+    new_index: Option<String>,
+
+    /// Rollover conditions (e.g. `{"max_age": "7d", "max_primary_shard_size": "50gb"}`).
+    /// Rollover is unconditional if missing.
+    conditions: Option<Map<String, Value>>,
+
+    /// Apply the change. If false or missing, only a preview of the change is returned
+    apply: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct PutIlmPolicyParams {
+    /// Name of the policy
+    name: String,
+
+    /// Policy definition (e.g. `{"phases": {"hot": {"actions": {"rollover": {"max_age": "7d"}}}}}`)
+    policy: Map<String, Value>,
+
+    /// Apply the change. If false or missing, only a preview of the change is returned
+    apply: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct CancelTaskParams {
+    /// Id of the task, as `node_id:task_number` (e.g. "oTUltX4IQMOUUVeiohTt8A:12345")
+    task_id: String,
+
+    /// Cancel the task. If false or missing, only a preview of the task is returned
+    apply: Option<bool>,
+}
+
+#[derive(serde::Deserialize)]
+struct GetTaskResponse {
+    #[serde(default)]
+    completed: bool,
+    task: TaskInfo,
+}
+
+#[tool_router(router = admin_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: create an index
+    #[tool(
+        description = "Create an Elasticsearch index with optional settings, mappings and aliases. \
+            Returns a preview unless called with `apply: true`.",
+        annotations(title = "Create ES index", read_only_hint = false, destructive_hint = false)
+    )]
+    async fn create_index(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(CreateIndexParams {
+            index,
+            settings,
+            mappings,
+            aliases,
+            apply,
+        }): Parameters<CreateIndexParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let exists = es_client
+            .indices()
+            .exists(IndicesExistsParts::Index(&[&index]))
+            .send()
+            .await;
+        match exists {
+            Ok(response) if response.status_code() == StatusCode::NOT_FOUND => {
+                audit::record_es_request(&response);
+            }
+            // Other errors, such as missing privileges, must not be mistaken for a missing index
+            response => {
+                handle_error(response).await?;
+                return Ok(invalid(format!("Index [{index}] already exists.")));
+            }
+        }
+
+        let mut body = Map::new();
+        for (key, value) in [("settings", settings), ("mappings", mappings), ("aliases", aliases)] {
+            if let Some(value) = value {
+                body.insert(key.to_string(), Value::Object(value));
+            }
+        }
+        let body = Value::Object(body);
+
+        let what = format!("index [{index}]");
+        if !apply.unwrap_or(false) {
+            return Ok(preview(&what, json_diff::diff(&json!({}), &body)));
+        }
+
+        let response = es_client
+            .indices()
+            .create(IndicesCreateParts::Index(&index))
+            .body(body)
+            .send()
+            .await;
+
+        applied(&what, read_json(response).await?)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: add fields to a mapping
+    #[tool(
+        description = "Add or update fields in the mappings of Elasticsearch indices. Existing field types can't be \
+            changed. Returns a preview unless called with `apply: true`.",
+        annotations(title = "Update ES index mappings", read_only_hint = false, destructive_hint = false)
+    )]
+    async fn put_mapping(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(PutMappingParams {
+            index,
+            properties,
+            apply,
+        }): Parameters<PutMappingParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let response = es_client
+            .indices()
+            .get_mapping(IndicesGetMappingParts::Index(&[&index]))
+            .send()
+            .await;
+        let current: Map<String, Value> = read_json(response).await?;
+
+        let update = json!({ "properties": properties });
+        let mut conflicts = Vec::new();
+        let mut changes = Vec::new();
+
+        for (index_name, mappings) in &current {
+            let current_mapping = mappings.get("mappings").cloned().unwrap_or(json!({}));
+            if let Some(Value::Object(current_props)) = current_mapping.get("properties") {
+                mapping_conflicts(current_props, &properties, "", &mut conflicts);
+            }
+
+            let mut new_mapping = current_mapping.clone();
+            json_diff::merge(&mut new_mapping, &update);
+            changes.extend(prefixed(index_name, json_diff::diff(&current_mapping, &new_mapping)));
+        }
+
+        if !conflicts.is_empty() {
+            conflicts.sort();
+            conflicts.dedup();
+            return Ok(invalid(conflicts.join("\n")));
+        }
+
+        let what = format!("mappings of [{index}]");
+        if !apply.unwrap_or(false) {
+            return Ok(preview(&what, changes));
+        }
+
+        let response = es_client
+            .indices()
+            .put_mapping(IndicesPutMappingParts::Index(&[&index]))
+            .body(update)
+            .send()
+            .await;
+
+        applied(&what, read_json(response).await?)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: update index settings
+    #[tool(
+        description = "Update dynamic settings of Elasticsearch indices. \
+            Returns a preview unless called with `apply: true`.",
+        annotations(title = "Update ES index settings", read_only_hint = false, destructive_hint = true)
+    )]
+    async fn update_settings(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(UpdateSettingsParams { index, settings, apply }): Parameters<UpdateSettingsParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let response = es_client
+            .indices()
+            .get_settings(IndicesGetSettingsParts::Index(&[&index]))
+            .flat_settings(true)
+            .send()
+            .await;
+        let current: Map<String, Value> = read_json(response).await?;
+
+        let update = flatten_settings(&settings);
+        let mut errors = Vec::new();
+        let mut changes = Vec::new();
+
+        for (index_name, index_settings) in &current {
+            let current_settings = index_settings.get("settings").cloned().unwrap_or(json!({}));
+
+            for (name, value) in &update {
+                if is_static_setting(name) && current_settings.get(name) != Some(value) {
+                    errors.push(format!(
+                        "[{name}] is a static setting and can't be changed on [{index_name}]"
+                    ));
+                }
+            }
+
+            let mut new_settings = current_settings.clone();
+            json_diff::merge(&mut new_settings, &Value::Object(update.clone()));
+            changes.extend(prefixed(index_name, json_diff::diff(&current_settings, &new_settings)));
+        }
+
+        if !errors.is_empty() {
+            return Ok(invalid(errors.join("\n")));
+        }
+
+        let what = format!("settings of [{index}]");
+        if !apply.unwrap_or(false) {
+            let checked = !changes.is_empty();
+            let mut result = preview(&what, changes);
+            if checked {
+                result.content.push(Content::text(
+                    "Only well-known static settings are checked: Elasticsearch may still reject other static settings.",
+                ));
+            }
+            return Ok(result);
+        }
+
+        let response = es_client
+            .indices()
+            .put_settings(IndicesPutSettingsParts::Index(&[&index]))
+            .body(Value::Object(update))
+            .send()
+            .await;
+
+        applied(&what, read_json(response).await?)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: create or replace an index template
+    #[tool(
+        description = "Create or replace a composable index template. \
+            Returns a preview unless called with `apply: true`.",
+        annotations(title = "Put ES index template", read_only_hint = false, destructive_hint = true)
+    )]
+    async fn put_index_template(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(PutTemplateParams { name, template, apply }): Parameters<PutTemplateParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        match template.get("index_patterns") {
+            Some(Value::String(_)) => {}
+            Some(Value::Array(patterns)) if !patterns.is_empty() => {}
+            _ => {
+                return Ok(invalid(
+                    "An index template requires non-empty `index_patterns`.".to_string(),
+                ));
+            }
+        }
+
+        let response = es_client
+            .indices()
+            .get_index_template(IndicesGetIndexTemplateParts::Name(&name))
+            .send()
+            .await;
+        let current = read_json_opt::<Value>(response)
+            .await?
+            .and_then(|r| r.pointer("/index_templates/0/index_template").cloned())
+            .unwrap_or(json!({}));
+
+        let template = Value::Object(template);
+        let what = format!("index template [{name}]");
+        if !apply.unwrap_or(false) {
+            return Ok(preview(&what, json_diff::diff(&current, &template)));
+        }
+
+        let response = es_client
+            .indices()
+            .put_index_template(IndicesPutIndexTemplateParts::Name(&name))
+            .body(template)
+            .send()
+            .await;
+
+        applied(&what, read_json(response).await?)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: create or replace a component template
+    #[tool(
+        description = "Create or replace a component template. Returns a preview unless called with `apply: true`.",
+        annotations(title = "Put ES component template", read_only_hint = false, destructive_hint = true)
+    )]
+    async fn put_component_template(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(PutTemplateParams { name, template, apply }): Parameters<PutTemplateParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        if !template.contains_key("template") {
+            return Ok(invalid(
+                "A component template requires a `template` property.".to_string(),
+            ));
+        }
+
+        let response = es_client
+            .cluster()
+            .get_component_template(ClusterGetComponentTemplateParts::Name(&name))
+            .send()
+            .await;
+        let current = read_json_opt::<Value>(response)
+            .await?
+            .and_then(|r| r.pointer("/component_templates/0/component_template").cloned())
+            .unwrap_or(json!({}));
+
+        let template = Value::Object(template);
+        let what = format!("component template [{name}]");
+        if !apply.unwrap_or(false) {
+            return Ok(preview(&what, json_diff::diff(&current, &template)));
+        }
+
+        let response = es_client
+            .cluster()
+            .put_component_template(ClusterPutComponentTemplateParts::Name(&name))
+            .body(template)
+            .send()
+            .await;
+
+        applied(&what, read_json(response).await?)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: add or remove aliases
+    #[tool(
+        description = "Add or remove index aliases atomically. Returns a preview unless called with `apply: true`.",
+        annotations(title = "Manage ES aliases", read_only_hint = false, destructive_hint = true)
+    )]
+    async fn manage_alias(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(ManageAliasParams { actions, apply }): Parameters<ManageAliasParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        if actions.is_empty() {
+            return Ok(invalid("No alias actions provided.".to_string()));
+        }
+
+        let mut indices = actions.iter().map(|a| a.index.as_str()).collect::<Vec<_>>();
+        indices.sort();
+        indices.dedup();
+
+        let response = es_client
+            .indices()
+            .get_alias(IndicesGetAliasParts::Index(&indices))
+            .ignore_unavailable(true)
+            .send()
+            .await;
+        let current: Map<String, Value> = read_json(response).await?;
+
+        let mut errors = Vec::new();
+        let mut projected = current.clone();
+        let mut es_actions = Vec::new();
+
+        for AliasAction {
+            action,
+            index,
+            alias,
+            is_write_index,
+            filter,
+        } in actions
+        {
+            let Some(aliases) = projected
+                .get_mut(&index)
+                .and_then(|i| i.get_mut("aliases"))
+                .and_then(Value::as_object_mut)
+            else {
+                errors.push(format!("Index [{index}] doesn't exist."));
+                continue;
+            };
+
+            match action {
+                AliasActionType::Add => {
+                    let mut props = Map::new();
+                    if let Some(is_write_index) = is_write_index {
+                        props.insert("is_write_index".to_string(), json!(is_write_index));
+                    }
+                    if let Some(filter) = filter {
+                        props.insert("filter".to_string(), Value::Object(filter));
+                    }
+                    aliases.insert(alias.clone(), Value::Object(props.clone()));
+
+                    props.insert("index".to_string(), json!(index));
+                    props.insert("alias".to_string(), json!(alias));
+                    es_actions.push(json!({ "add": props }));
+                }
+                AliasActionType::Remove => {
+                    if aliases.remove(&alias).is_none() {
+                        errors.push(format!("Alias [{alias}] doesn't exist on index [{index}]."));
+                    }
+                    es_actions.push(json!({ "remove": { "index": index, "alias": alias } }));
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Ok(invalid(errors.join("\n")));
+        }
+
+        let what = "aliases".to_string();
+        if !apply.unwrap_or(false) {
+            let changes = json_diff::diff(&Value::Object(current), &Value::Object(projected));
+            return Ok(preview(&what, changes));
+        }
+
+        let response = es_client
+            .indices()
+            .update_aliases()
+            .body(json!({ "actions": es_actions }))
+            .send()
+            .await;
+
+        applied(&what, read_json(response).await?)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: roll over an alias or data stream
+    #[tool(
+        description = "Roll over an alias or data stream to a new index. \
+            Returns a preview unless called with `apply: true`.",
+        annotations(title = "Roll over ES alias", read_only_hint = false, destructive_hint = false)
+    )]
+    async fn rollover(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(RolloverParams {
+            alias,
+            new_index,
+            conditions,
+            apply,
+        }): Parameters<RolloverParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let parts = match &new_index {
+            Some(new_index) => IndicesRolloverParts::AliasNewIndex(&alias, new_index),
+            None => IndicesRolloverParts::Alias(&alias),
+        };
+
+        let mut body = Map::new();
+        if let Some(conditions) = conditions {
+            body.insert("conditions".to_string(), Value::Object(conditions));
+        }
+
+        // Elasticsearch validates the rollover and checks conditions in dry-run mode
+        let dry_run = !apply.unwrap_or(false);
+        let response = es_client
+            .indices()
+            .rollover(parts)
+            .dry_run(dry_run)
+            .body(Value::Object(body))
+            .send()
+            .await;
+        let response: Value = read_json(response).await?;
+
+        let what = format!("alias [{alias}]");
+        if dry_run {
+            let old_index = response.get("old_index").cloned().unwrap_or(Value::Null);
+            let new_index = response.get("new_index").cloned().unwrap_or(Value::Null);
+            let mut changes = vec![format!("~ write_index: {old_index} -> {new_index}")];
+            if let Some(Value::Object(conditions)) = response.get("conditions") {
+                for (condition, met) in conditions {
+                    changes.push(format!("  condition {condition}: {met}"));
+                }
+            }
+            return Ok(preview(&what, changes));
+        }
+
+        applied(&what, response)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: create or replace an ILM policy
+    #[tool(
+        description = "Create or replace an index lifecycle management (ILM) policy. \
+            Returns a preview unless called with `apply: true`.",
+        annotations(title = "Put ES ILM policy", read_only_hint = false, destructive_hint = true)
+    )]
+    async fn put_ilm_policy(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(PutIlmPolicyParams { name, policy, apply }): Parameters<PutIlmPolicyParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        if !matches!(policy.get("phases"), Some(Value::Object(phases)) if !phases.is_empty()) {
+            return Ok(invalid(
+                "An ILM policy requires at least one phase in `phases`.".to_string(),
+            ));
+        }
+
+        let response = es_client
+            .ilm()
+            .get_lifecycle(IlmGetLifecycleParts::Policy(&name))
+            .send()
+            .await;
+        let current = read_json_opt::<Map<String, Value>>(response)
+            .await?
+            .and_then(|mut r| r.remove(&name))
+            .and_then(|p| p.get("policy").cloned())
+            .unwrap_or(json!({}));
+
+        let policy = Value::Object(policy);
+        let what = format!("ILM policy [{name}]");
+        if !apply.unwrap_or(false) {
+            return Ok(preview(&what, json_diff::diff(&current, &policy)));
+        }
+
+        let response = es_client
+            .ilm()
+            .put_lifecycle(IlmPutLifecycleParts::Policy(&name))
+            .body(json!({ "policy": policy }))
+            .send()
+            .await;
+
+        applied(&what, read_json(response).await?)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: cancel a task
+    #[tool(
+        description = "Cancel a running task, such as a long running search found with `list_tasks`. \
+            Returns a preview unless called with `apply: true`.",
+        annotations(title = "Cancel ES task", read_only_hint = false, destructive_hint = true)
+    )]
+    async fn cancel_task(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(CancelTaskParams { task_id, apply }): Parameters<CancelTaskParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let response = es_client.tasks().get(TasksGetParts::TaskId(&task_id)).send().await;
+        let Some(current) = read_json_opt::<GetTaskResponse>(response).await? else {
+            return Ok(invalid(format!("task [{task_id}] doesn't exist.")));
+        };
+        if current.completed {
+            return Ok(invalid(format!("task [{task_id}] has already completed.")));
+        }
+        if !current.task.cancellable {
+            return Ok(invalid(format!("task [{task_id}] can't be cancelled.")));
+        }
+
+        let what = format!("task [{task_id}]");
+        if !apply.unwrap_or(false) {
+            return Ok(CallToolResult::success(vec![
+                Content::text("The following task will be cancelled. Call again with `apply: true` to cancel it:"),
+                Content::json(current.task.summary(None))?,
+            ]));
+        }
+
+        let response = es_client
+            .tasks()
+            .cancel(TasksCancelParts::TaskId(&task_id))
+            .send()
+            .await;
+        applied(&what, read_json(response).await?)
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Helper functions for admin tools
+
+/// Input that doesn't pass validation. It's returned to the client so that it can correct it.
+fn invalid(message: String) -> CallToolResult {
+    CallToolResult::error(vec![Content::text(format!("Validation failed: {message}"))])
+}
+
+/// Preview of the changes that would be applied
+fn preview(what: &str, changes: Vec<String>) -> CallToolResult {
+    if changes.is_empty() {
+        return CallToolResult::success(vec![Content::text(format!("No changes to {what}."))]);
+    }
+
+    CallToolResult::success(vec![
+        Content::text(format!(
+            "Preview of changes to {what}. Call again with `apply: true` to apply them:"
+        )),
+        Content::text(changes.join("\n")),
+    ])
+}
+
+fn applied(what: &str, response: Value) -> Result<CallToolResult, rmcp::Error> {
+    Ok(CallToolResult::success(vec![
+        Content::text(format!("Changes to {what} applied:")),
+        Content::json(response)?,
+    ]))
+}
+
+fn prefixed(index: &str, changes: Vec<String>) -> impl Iterator<Item = String> {
+    changes.into_iter().map(move |change| format!("[{index}] {change}"))
+}
+
+/// Find fields whose type would be changed by a mapping update, which Elasticsearch doesn't allow.
+fn mapping_conflicts(
+    current: &Map<String, Value>,
+    update: &Map<String, Value>,
+    prefix: &str,
+    conflicts: &mut Vec<String>,
+) {
+    for (name, new_field) in update {
+        let Some(old_field) = current.get(name) else {
+            continue;
+        };
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{prefix}.{name}")
+        };
+
+        let old_type = old_field.get("type").and_then(Value::as_str).unwrap_or("object");
+        let new_type = new_field.get("type").and_then(Value::as_str).unwrap_or("object");
+
+        if old_type != new_type {
+            conflicts.push(format!(
+                "field [{path}] can't be changed from type [{old_type}] to [{new_type}]"
+            ));
+        } else if let (Some(Value::Object(old_props)), Some(Value::Object(new_props))) =
+            (old_field.get("properties"), new_field.get("properties"))
+        {
+            mapping_conflicts(old_props, new_props, &path, conflicts);
+        }
+    }
+}
+
+/// Flatten nested settings to the `index.xxx` form returned by Elasticsearch with `flat_settings`.
+/// Scalar values are converted to strings, like Elasticsearch does.
+fn flatten_settings(settings: &Map<String, Value>) -> Map<String, Value> {
+    fn flatten_into(path: String, value: &Value, result: &mut Map<String, Value>) {
+        match value {
+            Value::Object(obj) => {
+                for (key, value) in obj {
+                    flatten_into(format!("{path}.{key}"), value, result);
+                }
+            }
+            Value::Null | Value::String(_) | Value::Array(_) => {
+                result.insert(path, value.clone());
+            }
+            _ => {
+                result.insert(path, Value::String(value.to_string()));
+            }
+        }
+    }
+
+    let mut result = Map::new();
+    for (key, value) in settings {
+        let path = if key == "index" || key.starts_with("index.") {
+            key.clone()
+        } else {
+            format!("index.{key}")
+        };
+        flatten_into(path, value, &mut result);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn field_type_conflicts() {
+        let current = object(json!({
+            "name": {"type": "text"},
+            "count": {"type": "long"},
+            "user": {"properties": {"id": {"type": "keyword"}, "age": {"type": "integer"}}}
+        }));
+        let update = object(json!({
+            "name": {"type": "text", "analyzer": "english"},
+            "count": {"type": "keyword"},
+            "user": {"properties": {"id": {"type": "long"}, "email": {"type": "keyword"}}},
+            "new_field": {"type": "date"}
+        }));
+
+        let mut conflicts = Vec::new();
+        mapping_conflicts(&current, &update, "", &mut conflicts);
+        assert_eq!(
+            vec![
+                "field [count] can't be changed from type [long] to [keyword]",
+                "field [user.id] can't be changed from type [keyword] to [long]",
+            ],
+            conflicts
+        );
+    }
+
+    #[test]
+    fn object_and_field_conflicts() {
+        let current = object(json!({
+            "user": {"properties": {"id": {"type": "keyword"}}},
+            "host": {"type": "keyword"},
+            "events": {"type": "nested", "properties": {"kind": {"type": "keyword"}}}
+        }));
+        let update = object(json!({
+            "user": {"type": "keyword"},
+            "host": {"properties": {"name": {"type": "keyword"}}},
+            "events": {"properties": {"kind": {"type": "keyword"}}}
+        }));
+
+        let mut conflicts = Vec::new();
+        mapping_conflicts(&current, &update, "", &mut conflicts);
+        assert_eq!(
+            vec![
+                "field [events] can't be changed from type [nested] to [object]",
+                "field [host] can't be changed from type [keyword] to [object]",
+                "field [user] can't be changed from type [object] to [keyword]",
+            ],
+            conflicts
+        );
+    }
+
+    #[test]
+    fn static_settings() {
+        assert!(is_static_setting("index.number_of_shards"));
+        assert!(is_static_setting("index.sort.field"));
+        assert!(!is_static_setting("index.sort"));
+        assert!(!is_static_setting("index.number_of_replicas"));
+        assert!(!is_static_setting("index.codecs"));
+    }
+
+    #[test]
+    fn flat_settings() {
+        let settings = object(json!({
+            "number_of_replicas": 2,
+            "refresh_interval": "30s",
+            "index": {"lifecycle": {"name": "logs"}},
+            "index.codec": "best_compression",
+            "analysis": {"analyzer": {"folding": {"tokenizer": "standard", "filter": ["lowercase"]}}},
+            "blocks": {"write": true}
+        }));
+
+        assert_eq!(
+            object(json!({
+                "index.number_of_replicas": "2",
+                "index.refresh_interval": "30s",
+                "index.lifecycle.name": "logs",
+                "index.codec": "best_compression",
+                "index.analysis.analyzer.folding.tokenizer": "standard",
+                "index.analysis.analyzer.folding.filter": ["lowercase"],
+                "index.blocks.write": "true"
+            })),
+            flatten_settings(&settings)
+        );
+    }
+}
@@ -0,0 +1,1161 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Analytics tools that answer quick questions with aggregations, returning small results
+//! instead of documents.
+
+use crate::servers::elasticsearch::base_tools::{EsBaseTools, SearchResult, parse_time_range};
+use crate::servers::elasticsearch::pagination::next_cursor_content;
+use crate::servers::elasticsearch::{pagination, read_json};
+use elasticsearch::{CountParts, SearchParts};
+use indexmap::IndexMap;
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+
+const DEFAULT_TIME_FIELD: &str = "@timestamp";
+
+const DEFAULT_DISTINCT_VALUES: u32 = 50;
+const MAX_DISTINCT_VALUES: u32 = 1000;
+
+const DEFAULT_TIME_SERIES_RANGE: &str = "now-24h";
+const DEFAULT_TIME_BUCKETS: u32 = 30;
+const MAX_TIME_BUCKETS: u32 = 200;
+const DEFAULT_SPLIT_SIZE: u32 = 5;
+const MAX_SPLIT_SIZE: u32 = 20;
+
+const DEFAULT_CHANGE_SPLIT_SIZE: u32 = 10;
+const MAX_CHANGE_SPLIT_SIZE: u32 = 50;
+
+/// Number of time buckets of change detection, which needs at least 22 buckets
+const CHANGE_POINT_BUCKETS: u32 = 100;
+
+/// Change point types that aren't changes
+const NO_CHANGE_TYPES: [&str; 3] = ["stationary", "non_stationary", "indeterminable"];
+
+/// Number of buckets averaged by the moving average pipeline
+const MOVING_AVERAGE_WINDOW: u32 = 5;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct CountParams {
+    /// Name or pattern of the indices to count documents in
+    index: String,
+
+    /// Query DSL query the documents must match (optional, default: all documents)
+    query: Option<Map<String, Value>>,
+
+    /// Time range of the documents (e.g., "now-1h", "now-1d", "2024-01-01T00:00:00Z,2024-01-01T23:59:59Z")
+    /// (optional, default: all time)
+    time_range: Option<String>,
+
+    /// Timestamp field of the time range (optional, default: "@timestamp")
+    time_field: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct DistinctValuesParams {
+    /// Name or pattern of the indices
+    index: String,
+
+    /// Field whose values are returned, usually a `keyword` field (e.g., "host.name")
+    field: String,
+
+    /// Query DSL query the documents must match (optional, default: all documents)
+    query: Option<Map<String, Value>>,
+
+    /// Time range of the documents (e.g., "now-1h", "now-1d", "2024-01-01T00:00:00Z,2024-01-01T23:59:59Z")
+    /// (optional, default: all time)
+    time_range: Option<String>,
+
+    /// Timestamp field of the time range (optional, default: "@timestamp")
+    time_field: Option<String>,
+
+    /// Order of the values: the most frequent first, or all values by value, paginated with a cursor
+    /// (optional, default: count)
+    order: Option<DistinctOrder>,
+
+    /// Maximum number of values to return (optional, default: 50, max: 1000)
+    size: Option<u32>,
+
+    /// Cursor returned as `next_cursor` by a previous call with the same arguments and `order: value`, to get
+    /// the next values
+    cursor: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct TimeSeriesParams {
+    /// Name or pattern of the indices
+    index: String,
+
+    /// Time range of the series (e.g., "now-1h", "now-7d", "2024-01-01T00:00:00Z,2024-01-01T23:59:59Z")
+    /// (optional, default: "now-24h")
+    time_range: Option<String>,
+
+    /// Timestamp field (optional, default: "@timestamp")
+    time_field: Option<String>,
+
+    /// Metrics computed in each time bucket (optional, default: document count)
+    metrics: Option<Vec<Metric>>,
+
+    /// Fixed interval of time buckets (e.g., "1m", "1h", "1d") or calendar interval ("1w", "1M", "1q", "1y")
+    /// (optional, default: chosen automatically to have about `buckets` buckets)
+    interval: Option<String>,
+
+    /// Target number of time buckets when the interval is chosen automatically (optional, default: 30, max: 200)
+    buckets: Option<u32>,
+
+    /// Field whose most frequent values each have their own series, usually a `keyword` field (e.g.,
+    /// "service.name") (optional)
+    split_by: Option<String>,
+
+    /// Number of values of `split_by` that have a series (optional, default: 5, max: 20)
+    split_size: Option<u32>,
+
+    /// Pipeline computed on each metric (optional)
+    pipeline: Option<Pipeline>,
+
+    /// Query DSL query the documents must match (optional, default: all documents)
+    query: Option<Map<String, Value>>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct DetectChangesParams {
+    /// Name or pattern of the indices
+    index: String,
+
+    /// Metric in which to detect changes (optional, default: document count)
+    metric: Option<Metric>,
+
+    /// Time range (e.g., "now-1h", "now-1d", "2024-01-01T12:00:00Z,2024-01-01T16:00:00Z")
+    /// (optional, default: "now-24h")
+    time_range: Option<String>,
+
+    /// Timestamp field (optional, default: "@timestamp")
+    time_field: Option<String>,
+
+    /// Fixed interval of time buckets (e.g., "1m", "1h"), at least 22 buckets are needed (optional, default:
+    /// chosen automatically to have about 100 buckets)
+    interval: Option<String>,
+
+    /// Field whose most frequent values are analyzed separately, usually a `keyword` field (e.g.,
+    /// "service.name", "host.name") (optional)
+    split_by: Option<String>,
+
+    /// Number of values of `split_by` that are analyzed (optional, default: 10, max: 50)
+    split_size: Option<u32>,
+
+    /// Query DSL query the documents must match (optional, default: all documents)
+    query: Option<Map<String, Value>>,
+}
+
+/// A metric computed in each bucket of an aggregation.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+pub(super) struct Metric {
+    /// Aggregation (optional, default: count)
+    #[serde(default)]
+    pub aggregation: MetricAggregation,
+
+    /// Numeric field of the aggregation, not needed for count (e.g., "system.cpu.total.pct")
+    pub field: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum MetricAggregation {
+    #[default]
+    Count,
+    Avg,
+    Sum,
+    Min,
+    Max,
+    Cardinality,
+    P50,
+    P90,
+    P95,
+    P99,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum Pipeline {
+    /// Change of each metric from the previous bucket
+    Derivative,
+    /// Average of each metric over the last 5 buckets
+    MovingAverage,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum DistinctOrder {
+    #[default]
+    Count,
+    Value,
+}
+
+#[tool_router(router = analytics_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: count
+    #[tool(
+        description = "Count the documents matching a query and/or time range. Use this rather than a search \
+            to answer \"how many\" questions.",
+        annotations(title = "Count ES documents", read_only_hint = true)
+    )]
+    async fn count(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(CountParams {
+            index,
+            query,
+            time_range,
+            time_field,
+        }): Parameters<CountParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let time_field = time_field.as_deref().unwrap_or(DEFAULT_TIME_FIELD);
+        let query = filtered_query(query, time_range.as_deref(), time_field)?;
+
+        let response = es_client
+            .count(CountParts::Index(&[&index]))
+            .body(json!({ "query": query }))
+            .send()
+            .await;
+        let response: CountResponse = read_json(response).await?;
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "{} documents in {index}{}.",
+            response.count,
+            describe_time_range(time_range.as_deref())
+        ))]))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: distinct values
+    #[tool(
+        description = "List the distinct values of a field with their document count, e.g. the hosts that \
+            reported errors. Returns the most frequent values, or all values paginated with a cursor.",
+        annotations(title = "Distinct values of an ES field", read_only_hint = true)
+    )]
+    async fn distinct_values(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(DistinctValuesParams {
+            index,
+            field,
+            query,
+            time_range,
+            time_field,
+            order,
+            size,
+            cursor,
+        }): Parameters<DistinctValuesParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let order = order.unwrap_or_default();
+        if order == DistinctOrder::Count && cursor.is_some() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "Cursors are only supported with `order: value`.",
+            )]));
+        }
+
+        let time_field = time_field.as_deref().unwrap_or(DEFAULT_TIME_FIELD);
+        let query = filtered_query(query, time_range.as_deref(), time_field)?;
+        let size = size.unwrap_or(DEFAULT_DISTINCT_VALUES).clamp(1, MAX_DISTINCT_VALUES);
+        let query_body = distinct_values_request(&field, query, order, size);
+
+        let (response, next_cursor) = match order {
+            DistinctOrder::Count => {
+                let response = es_client
+                    .search(SearchParts::Index(&[&index]))
+                    .body(query_body)
+                    .send()
+                    .await;
+                (read_json(response).await?, None)
+            }
+            DistinctOrder::Value => {
+                pagination::composite_search(&es_client, &index, query_body, "values", cursor.as_deref()).await?
+            }
+        };
+
+        let values = response.aggregations.get("values").cloned().unwrap_or_default();
+        let values: DistinctValues = serde_json::from_value(values).unwrap_or_default();
+        let distinct = response
+            .aggregations
+            .get("distinct")
+            .and_then(|agg| agg.get("value"))
+            .and_then(Value::as_u64)
+            .unwrap_or_default();
+
+        let mut results = vec![Content::text(format!(
+            "About {distinct} distinct values of {field} in {index}{}:",
+            describe_time_range(time_range.as_deref())
+        ))];
+        let other_docs = values.sum_other_doc_count;
+        self.push_rows("distinct_values", values.rows(), &mut results)?;
+        if other_docs > 0 {
+            results.push(Content::text(format!(
+                "{other_docs} other documents have other values, use `order: value` to page through all values."
+            )));
+        }
+        if let Some(cursor) = next_cursor {
+            results.push(next_cursor_content(&cursor)?);
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: time series
+    #[tool(
+        description = "Compute the trend of metrics over time with a date histogram, optionally split by the \
+            values of a field, and with a derivative or moving average. Returns a table with a row per time \
+            bucket, with the column names in the first row.",
+        annotations(title = "ES time series", read_only_hint = true)
+    )]
+    async fn time_series(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(TimeSeriesParams {
+            index,
+            time_range,
+            time_field,
+            metrics,
+            interval,
+            buckets,
+            split_by,
+            split_size,
+            pipeline,
+            query,
+        }): Parameters<TimeSeriesParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let time_range = time_range.as_deref().unwrap_or(DEFAULT_TIME_SERIES_RANGE);
+        let time_field = time_field.as_deref().unwrap_or(DEFAULT_TIME_FIELD);
+        let (start, end) = parse_time_range(time_range)?;
+
+        let metrics = metrics.unwrap_or_else(|| vec![Metric::count()]);
+        if let Some(error) = metrics
+            .iter()
+            .find_map(Metric::check)
+            .or_else(|| interval.as_deref().and_then(check_interval))
+        {
+            return Ok(CallToolResult::error(vec![Content::text(error)]));
+        }
+
+        let series = TimeSeries {
+            time_field: time_field.to_string(),
+            start,
+            end,
+            interval,
+            buckets: buckets.unwrap_or(DEFAULT_TIME_BUCKETS).clamp(1, MAX_TIME_BUCKETS),
+            metrics,
+            split_by,
+            split_size: split_size.unwrap_or(DEFAULT_SPLIT_SIZE).clamp(1, MAX_SPLIT_SIZE),
+            pipeline,
+        };
+        let query = filtered_query(query, Some(time_range), time_field)?;
+
+        let response = es_client
+            .search(SearchParts::Index(&[&index]))
+            .body(series.request(query))
+            .send()
+            .await;
+        let response: SearchResult = read_json(response).await?;
+
+        let (rows, interval) = series.rows(&response.aggregations);
+        let labels = series.metrics.iter().map(Metric::label).collect::<Vec<_>>().join(", ");
+        let interval = interval.map(|i| format!(", by {i}")).unwrap_or_default();
+        let mut results = vec![Content::text(format!(
+            "Time series of {labels} in {index} between {} and {}{interval}:",
+            series.start, series.end
+        ))];
+        self.push_rows("time_series", rows, &mut results)?;
+
+        Ok(CallToolResult::success(results))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: detect changes
+    #[tool(
+        description = "Detect spikes, dips, step changes and trend changes of a metric over time, optionally \
+            for each value of a field such as a service or host. Returns the detected changes, the most \
+            significant (lowest p-value) first.",
+        annotations(title = "Detect changes in ES metrics", read_only_hint = true)
+    )]
+    async fn detect_changes(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(DetectChangesParams {
+            index,
+            metric,
+            time_range,
+            time_field,
+            interval,
+            split_by,
+            split_size,
+            query,
+        }): Parameters<DetectChangesParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let time_range = time_range.as_deref().unwrap_or(DEFAULT_TIME_SERIES_RANGE);
+        let time_field = time_field.as_deref().unwrap_or(DEFAULT_TIME_FIELD);
+        let (start, end) = parse_time_range(time_range)?;
+
+        let metric = metric.unwrap_or_else(Metric::count);
+        if let Some(error) = metric.check().or_else(|| interval.as_deref().and_then(check_interval)) {
+            return Ok(CallToolResult::error(vec![Content::text(error)]));
+        }
+
+        let detection = ChangeDetection {
+            metric,
+            split_by,
+            split_size: split_size
+                .unwrap_or(DEFAULT_CHANGE_SPLIT_SIZE)
+                .clamp(1, MAX_CHANGE_SPLIT_SIZE),
+        };
+        let histogram = date_histogram(
+            time_field,
+            interval.as_deref(),
+            CHANGE_POINT_BUCKETS,
+            (&start, &end),
+            detection.sub_aggs(),
+        );
+        let query = filtered_query(query, Some(time_range), time_field)?;
+
+        let response = es_client
+            .search(SearchParts::Index(&[&index]))
+            .body(detection.request(histogram, query))
+            .send()
+            .await;
+        let response: SearchResult = read_json(response).await?;
+
+        let (changes, unchanged) = detection.changes(&response.aggregations);
+        let label = detection.metric.label();
+        let mut results = match changes.len() {
+            0 => vec![Content::text(format!(
+                "No change detected in {label} of {index} between {start} and {end}."
+            ))],
+            n => vec![Content::text(format!(
+                "Detected {n} changes in {label} of {index} between {start} and {end}:"
+            ))],
+        };
+        if !changes.is_empty() {
+            self.push_rows("detect_changes", detection.rows(changes), &mut results)?;
+        }
+        if let Some(field) = &detection.split_by
+            && unchanged > 0
+        {
+            results.push(Content::text(format!("No change in {unchanged} values of {field}.")));
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Queries
+
+/// A query matching both `query` and `time_range` on `time_field`, any of them being optional.
+pub(super) fn filtered_query(
+    query: Option<Map<String, Value>>,
+    time_range: Option<&str>,
+    time_field: &str,
+) -> Result<Value, rmcp::Error> {
+    let mut filters = Vec::new();
+    if let Some(time_range) = time_range {
+        let (start, end) = parse_time_range(time_range)?;
+        filters.push(json!({ "range": { time_field: { "gte": start, "lte": end } } }));
+    }
+    if let Some(query) = query {
+        filters.push(Value::Object(query));
+    }
+
+    Ok(match filters.len() {
+        0 => json!({ "match_all": {} }),
+        1 => filters.remove(0),
+        _ => json!({ "bool": { "filter": filters } }),
+    })
+}
+
+/// Describe a time range in a sentence, e.g. " between now-1h and now".
+pub(super) fn describe_time_range(time_range: Option<&str>) -> String {
+    match time_range.map(parse_time_range) {
+        Some(Ok((start, end))) => format!(" between {start} and {end}"),
+        _ => String::new(),
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Count
+
+#[derive(Deserialize)]
+struct CountResponse {
+    count: u64,
+}
+
+//-------------------------------------------------------------------------------------------------
+// Distinct values
+
+fn distinct_values_request(field: &str, query: Value, order: DistinctOrder, size: u32) -> Map<String, Value> {
+    let values = match order {
+        DistinctOrder::Count => json!({ "terms": { "field": field, "size": size } }),
+        DistinctOrder::Value => json!({
+            "composite": { "size": size, "sources": [{ "value": { "terms": { "field": field } } }] }
+        }),
+    };
+
+    let mut body = Map::new();
+    body.insert("size".to_string(), json!(0));
+    body.insert("query".to_string(), query);
+    body.insert(
+        "aggs".to_string(),
+        json!({
+            "values": values,
+            "distinct": { "cardinality": { "field": field } }
+        }),
+    );
+    body
+}
+
+/// Buckets of a `terms` or `composite` aggregation.
+#[derive(Default, Deserialize)]
+struct DistinctValues {
+    #[serde(default)]
+    buckets: Vec<ValueBucket>,
+    #[serde(default)]
+    sum_other_doc_count: u64,
+}
+
+#[derive(Deserialize)]
+struct ValueBucket {
+    key: Value,
+    doc_count: u64,
+}
+
+impl DistinctValues {
+    fn rows(self) -> Vec<Value> {
+        self.buckets
+            .into_iter()
+            .map(|bucket| {
+                // Composite keys are objects with a value per source
+                let value = match bucket.key {
+                    Value::Object(mut key) => key.remove("value").unwrap_or_default(),
+                    key => key,
+                };
+                json!({ "value": value, "count": bucket.doc_count })
+            })
+            .collect()
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Metrics
+
+impl Metric {
+    pub fn count() -> Metric {
+        Metric {
+            aggregation: MetricAggregation::Count,
+            field: None,
+        }
+    }
+
+    /// An error message if the metric has no field but needs one.
+    pub fn check(&self) -> Option<String> {
+        (self.aggregation != MetricAggregation::Count && self.field.is_none())
+            .then(|| format!("A field is required for the {} aggregation.", self.aggregation.name()))
+    }
+
+    /// Column name of the metric, e.g. `avg(system.cpu.total.pct)`
+    pub fn label(&self) -> String {
+        match &self.field {
+            Some(field) if self.aggregation != MetricAggregation::Count => {
+                format!("{}({field})", self.aggregation.name())
+            }
+            _ => self.aggregation.name().to_string(),
+        }
+    }
+
+    fn percent(&self) -> Option<f64> {
+        match self.aggregation {
+            MetricAggregation::P50 => Some(50.0),
+            MetricAggregation::P90 => Some(90.0),
+            MetricAggregation::P95 => Some(95.0),
+            MetricAggregation::P99 => Some(99.0),
+            _ => None,
+        }
+    }
+
+    /// The sub-aggregation computing the metric, if any: counts are the `doc_count` of buckets.
+    pub fn sub_aggregation(&self) -> Option<Value> {
+        let field = self.field.as_deref()?;
+        if let Some(percent) = self.percent() {
+            return Some(json!({ "percentiles": { "field": field, "percents": [percent], "keyed": false } }));
+        }
+        match self.aggregation {
+            MetricAggregation::Count => None,
+            agg => Some(json!({ agg.name(): { "field": field } })),
+        }
+    }
+
+    /// Path of the metric's value for pipeline aggregations, when its sub-aggregation is `name`.
+    pub fn buckets_path(&self, name: &str) -> String {
+        if self.aggregation == MetricAggregation::Count {
+            return "_count".to_string();
+        }
+        match self.percent() {
+            Some(percent) => format!("{name}[{percent:.1}]"),
+            None => name.to_string(),
+        }
+    }
+
+    /// Value of the metric in a bucket, when its sub-aggregation is `name`.
+    pub fn value(&self, bucket: &Value, name: &str) -> Value {
+        if self.aggregation == MetricAggregation::Count {
+            return bucket.get("doc_count").cloned().unwrap_or_default();
+        }
+        let agg = bucket.get(name);
+        match self.percent() {
+            Some(_) => agg.and_then(|a| a.pointer("/values/0/value")).cloned(),
+            None => agg.and_then(|a| a.get("value")).cloned(),
+        }
+        .unwrap_or_default()
+    }
+}
+
+impl MetricAggregation {
+    fn name(self) -> &'static str {
+        match self {
+            MetricAggregation::Count => "count",
+            MetricAggregation::Avg => "avg",
+            MetricAggregation::Sum => "sum",
+            MetricAggregation::Min => "min",
+            MetricAggregation::Max => "max",
+            MetricAggregation::Cardinality => "cardinality",
+            MetricAggregation::P50 => "p50",
+            MetricAggregation::P90 => "p90",
+            MetricAggregation::P95 => "p95",
+            MetricAggregation::P99 => "p99",
+        }
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Time series
+
+/// Name of the `i`th metric sub-aggregation
+fn metric_name(i: usize) -> String {
+    format!("m{i}")
+}
+
+struct TimeSeries {
+    time_field: String,
+    start: String,
+    end: String,
+    interval: Option<String>,
+    buckets: u32,
+    metrics: Vec<Metric>,
+    split_by: Option<String>,
+    split_size: u32,
+    pipeline: Option<Pipeline>,
+}
+
+impl TimeSeries {
+    fn request(&self, query: Value) -> Map<String, Value> {
+        let mut sub_aggs = Map::new();
+        for (i, metric) in self.metrics.iter().enumerate() {
+            let name = metric_name(i);
+            if let Some(agg) = metric.sub_aggregation() {
+                sub_aggs.insert(name.clone(), agg);
+            }
+            let path = metric.buckets_path(&name);
+            match self.pipeline {
+                Some(Pipeline::Derivative) => {
+                    sub_aggs.insert(
+                        format!("{name}_derivative"),
+                        json!({ "derivative": { "buckets_path": path } }),
+                    );
+                }
+                Some(Pipeline::MovingAverage) => {
+                    sub_aggs.insert(
+                        format!("{name}_moving_avg"),
+                        json!({ "moving_fn": {
+                            "buckets_path": path,
+                            "window": MOVING_AVERAGE_WINDOW,
+                            "shift": 1,
+                            "script": "MovingFunctions.unweightedAvg(values)"
+                        }}),
+                    );
+                }
+                None => {}
+            }
+        }
+
+        let histogram = date_histogram(
+            &self.time_field,
+            self.interval.as_deref(),
+            self.buckets,
+            (&self.start, &self.end),
+            sub_aggs,
+        );
+
+        let aggs = match &self.split_by {
+            Some(field) => json!({
+                "split": {
+                    "terms": { "field": field, "size": self.split_size },
+                    "aggs": { "series": histogram }
+                }
+            }),
+            None => json!({ "series": histogram }),
+        };
+
+        let mut body = Map::new();
+        body.insert("size".to_string(), json!(0));
+        body.insert("query".to_string(), query);
+        body.insert("aggs".to_string(), aggs);
+        body
+    }
+
+    /// Column names of the table
+    fn columns(&self) -> Vec<String> {
+        let mut columns = vec!["time".to_string()];
+        columns.extend(self.split_by.clone());
+        for metric in &self.metrics {
+            let label = metric.label();
+            match self.pipeline {
+                Some(Pipeline::Derivative) => columns.extend([label.clone(), format!("derivative({label})")]),
+                Some(Pipeline::MovingAverage) => columns.extend([label.clone(), format!("moving_avg({label})")]),
+                None => columns.push(label),
+            }
+        }
+        columns
+    }
+
+    /// The table of the response, with the column names in the first row, and the interval of its
+    /// buckets if it was chosen automatically.
+    fn rows(&self, aggregations: &IndexMap<String, Value>) -> (Vec<Value>, Option<String>) {
+        let mut rows = vec![json!(self.columns())];
+        let mut interval = self.interval.clone();
+
+        let series = match aggregations.get("split").and_then(|split| split.get("buckets")) {
+            Some(Value::Array(splits)) => splits
+                .iter()
+                .map(|split| (split.get("key").cloned(), split.get("series")))
+                .collect::<Vec<_>>(),
+            _ => vec![(None, aggregations.get("series"))],
+        };
+
+        for (key, histogram) in series {
+            let Some(histogram) = histogram else { continue };
+            if let Some(auto_interval) = histogram.get("interval").and_then(Value::as_str) {
+                interval = Some(auto_interval.to_string());
+            }
+            let buckets = histogram.get("buckets").and_then(Value::as_array).into_iter().flatten();
+            for bucket in buckets {
+                let mut row = vec![bucket_time(bucket)];
+                row.extend(key.clone());
+                for (i, metric) in self.metrics.iter().enumerate() {
+                    let name = metric_name(i);
+                    row.push(metric.value(bucket, &name));
+                    let pipeline = match self.pipeline {
+                        Some(Pipeline::Derivative) => format!("{name}_derivative"),
+                        Some(Pipeline::MovingAverage) => format!("{name}_moving_avg"),
+                        None => continue,
+                    };
+                    row.push(
+                        bucket
+                            .get(&pipeline)
+                            .and_then(|p| p.get("value"))
+                            .cloned()
+                            .unwrap_or_default(),
+                    );
+                }
+                rows.push(Value::Array(row));
+            }
+        }
+
+        (rows, interval)
+    }
+}
+
+/// A date histogram on `time_field`, with a fixed or calendar `interval`, or about `buckets` buckets
+/// if there's no interval.
+fn date_histogram(
+    time_field: &str,
+    interval: Option<&str>,
+    buckets: u32,
+    (start, end): (&str, &str),
+    sub_aggs: Map<String, Value>,
+) -> Value {
+    match interval {
+        Some(interval) => {
+            let interval_type = if is_calendar_interval(interval) {
+                "calendar_interval"
+            } else {
+                "fixed_interval"
+            };
+            json!({
+                "date_histogram": {
+                    "field": time_field,
+                    interval_type: interval,
+                    "min_doc_count": 0,
+                    "extended_bounds": { "min": start, "max": end }
+                },
+                "aggs": sub_aggs
+            })
+        }
+        None => json!({
+            "auto_date_histogram": { "field": time_field, "buckets": buckets },
+            "aggs": sub_aggs
+        }),
+    }
+}
+
+/// Time of a date histogram bucket.
+fn bucket_time(bucket: &Value) -> Value {
+    bucket
+        .get("key_as_string")
+        .or_else(|| bucket.get("key"))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Is this a calendar interval, whose buckets don't all have the same duration?
+fn is_calendar_interval(interval: &str) -> bool {
+    matches!(
+        interval,
+        "1w" | "1M" | "1q" | "1y" | "week" | "month" | "quarter" | "year"
+    )
+}
+
+/// An error message if the interval is a multiple of a calendar unit, e.g. `2w`: calendar
+/// intervals can only be a single unit, and fixed intervals don't support these units.
+fn check_interval(interval: &str) -> Option<String> {
+    let unit = interval.chars().last()?;
+    let amount = &interval[..interval.len() - unit.len_utf8()];
+    (matches!(unit, 'w' | 'M' | 'q' | 'y') && amount != "1").then(|| {
+        format!(
+            "Invalid interval [{interval}]: weeks, months, quarters and years can only be used as a single \
+            unit (e.g. \"1{unit}\"). Use a fixed interval in days for other durations (e.g. \"14d\")."
+        )
+    })
+}
+
+//-------------------------------------------------------------------------------------------------
+// Change detection
+
+struct ChangeDetection {
+    metric: Metric,
+    split_by: Option<String>,
+    split_size: u32,
+}
+
+/// A change detected by a `change_point` aggregation.
+#[derive(Debug, PartialEq)]
+struct Change {
+    /// Value of the split field
+    key: Option<Value>,
+    kind: String,
+    p_value: f64,
+    time: Value,
+    value: Value,
+    /// Value in the bucket before the change
+    previous_value: Value,
+}
+
+impl ChangeDetection {
+    fn sub_aggs(&self) -> Map<String, Value> {
+        let mut sub_aggs = Map::new();
+        if let Some(agg) = self.metric.sub_aggregation() {
+            sub_aggs.insert(metric_name(0), agg);
+        }
+        sub_aggs
+    }
+
+    fn request(&self, histogram: Value, query: Value) -> Map<String, Value> {
+        let buckets_path = format!("series>{}", self.metric.buckets_path(&metric_name(0)));
+        let aggs = json!({
+            "series": histogram,
+            "change": { "change_point": { "buckets_path": buckets_path } }
+        });
+        let aggs = match &self.split_by {
+            Some(field) => json!({
+                "split": {
+                    "terms": { "field": field, "size": self.split_size },
+                    "aggs": aggs
+                }
+            }),
+            None => aggs,
+        };
+
+        let mut body = Map::new();
+        body.insert("size".to_string(), json!(0));
+        body.insert("query".to_string(), query);
+        body.insert("aggs".to_string(), aggs);
+        body
+    }
+
+    /// The detected changes, the most significant first, and the number of series without changes.
+    fn changes(&self, aggregations: &IndexMap<String, Value>) -> (Vec<Change>, usize) {
+        let series = match aggregations.get("split").and_then(|split| split.get("buckets")) {
+            Some(Value::Array(splits)) => splits
+                .iter()
+                .map(|split| (split.get("key").cloned(), split.get("series"), split.get("change")))
+                .collect(),
+            _ => vec![(None, aggregations.get("series"), aggregations.get("change"))],
+        };
+
+        let mut changes = Vec::new();
+        let mut unchanged = 0;
+        for (key, histogram, change_point) in series {
+            match self.change(key, histogram, change_point) {
+                Some(change) => changes.push(change),
+                None => unchanged += 1,
+            }
+        }
+
+        changes.sort_by(|a, b| a.p_value.total_cmp(&b.p_value));
+        (changes, unchanged)
+    }
+
+    fn change(&self, key: Option<Value>, histogram: Option<&Value>, change_point: Option<&Value>) -> Option<Change> {
+        let change_point = change_point?;
+        // The type is an object with a single key, e.g. `{"spike": {"p_value": 0.001, "change_point": 42}}`
+        let (kind, details) = change_point.get("type")?.as_object()?.iter().next()?;
+        if NO_CHANGE_TYPES.contains(&kind.as_str()) {
+            return None;
+        }
+
+        let name = metric_name(0);
+        let bucket = change_point.get("bucket");
+        let previous_value = details
+            .get("change_point")
+            .and_then(Value::as_u64)
+            .and_then(|i| i.checked_sub(1))
+            .and_then(|i| histogram?.get("buckets")?.get(i as usize))
+            .map(|previous| self.metric.value(previous, &name))
+            .unwrap_or_default();
+
+        Some(Change {
+            key,
+            kind: kind.clone(),
+            p_value: details.get("p_value").and_then(Value::as_f64).unwrap_or(1.0),
+            time: bucket.map(bucket_time).unwrap_or_default(),
+            value: bucket.map(|b| self.metric.value(b, &name)).unwrap_or_default(),
+            previous_value,
+        })
+    }
+
+    /// Table of changes, with the column names in the first row.
+    fn rows(&self, changes: Vec<Change>) -> Vec<Value> {
+        let mut columns = vec!["p_value", "change", "time"];
+        columns.extend(self.split_by.as_deref());
+        columns.extend(["value", "previous_value"]);
+
+        let mut rows = vec![json!(columns)];
+        for change in changes {
+            let mut row = vec![json!(change.p_value), json!(change.kind), change.time];
+            row.extend(change.key);
+            row.extend([change.value, change.previous_value]);
+            rows.push(Value::Array(row));
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filtered_queries() {
+        assert_eq!(
+            json!({"match_all": {}}),
+            filtered_query(None, None, "@timestamp").unwrap()
+        );
+
+        let query = json!({"term": {"log.level": "error"}}).as_object().cloned();
+        assert_eq!(
+            json!({"bool": {"filter": [
+                {"range": {"event.created": {"gte": "now-1d", "lte": "now"}}},
+                {"term": {"log.level": "error"}}
+            ]}}),
+            filtered_query(query, Some("now-1d"), "event.created").unwrap()
+        );
+    }
+
+    #[test]
+    fn intervals() {
+        assert!(is_calendar_interval("1M"));
+        assert!(is_calendar_interval("week"));
+        assert!(!is_calendar_interval("1d"));
+
+        assert_eq!(None, check_interval("1w"));
+        assert_eq!(None, check_interval("30m"));
+        assert_eq!(None, check_interval("quarter"));
+        assert!(check_interval("2w").unwrap().contains("[2w]"));
+        assert!(check_interval("3M").is_some());
+    }
+
+    #[test]
+    fn distinct_value_rows() {
+        let terms: DistinctValues = serde_json::from_value(json!({
+            "sum_other_doc_count": 3,
+            "buckets": [{"key": "web-1", "doc_count": 10}, {"key": "web-2", "doc_count": 4}]
+        }))
+        .unwrap();
+        assert_eq!(
+            vec![
+                json!({"value": "web-1", "count": 10}),
+                json!({"value": "web-2", "count": 4})
+            ],
+            terms.rows()
+        );
+
+        let composite: DistinctValues = serde_json::from_value(json!({
+            "after_key": {"value": 404},
+            "buckets": [{"key": {"value": 200}, "doc_count": 100}, {"key": {"value": 404}, "doc_count": 2}]
+        }))
+        .unwrap();
+        assert_eq!(0, composite.sum_other_doc_count);
+        assert_eq!(json!({"value": 404, "count": 2}), composite.rows()[1]);
+    }
+
+    fn series(pipeline: Option<Pipeline>) -> TimeSeries {
+        TimeSeries {
+            time_field: "@timestamp".to_string(),
+            start: "now-1h".to_string(),
+            end: "now".to_string(),
+            interval: Some("30m".to_string()),
+            buckets: DEFAULT_TIME_BUCKETS,
+            metrics: vec![
+                Metric::count(),
+                Metric {
+                    aggregation: MetricAggregation::P95,
+                    field: Some("duration".to_string()),
+                },
+            ],
+            split_by: Some("host.name".to_string()),
+            split_size: 2,
+            pipeline,
+        }
+    }
+
+    #[test]
+    fn time_series_request() {
+        let request = series(Some(Pipeline::Derivative)).request(json!({"match_all": {}}));
+        assert_eq!(
+            json!({
+                "split": {
+                    "terms": {"field": "host.name", "size": 2},
+                    "aggs": {"series": {
+                        "date_histogram": {
+                            "field": "@timestamp",
+                            "fixed_interval": "30m",
+                            "min_doc_count": 0,
+                            "extended_bounds": {"min": "now-1h", "max": "now"}
+                        },
+                        "aggs": {
+                            "m0_derivative": {"derivative": {"buckets_path": "_count"}},
+                            "m1": {"percentiles": {"field": "duration", "percents": [95.0], "keyed": false}},
+                            "m1_derivative": {"derivative": {"buckets_path": "m1[95.0]"}}
+                        }
+                    }}
+                }
+            }),
+            request["aggs"]
+        );
+    }
+
+    #[test]
+    fn time_series_rows() {
+        let aggregations: IndexMap<String, Value> = serde_json::from_value(json!({
+            "split": {"buckets": [{
+                "key": "web-1",
+                "doc_count": 5,
+                "series": {"buckets": [
+                    {"key_as_string": "2025-01-01T10:00:00Z", "doc_count": 2, "m1": {"values": [{"key": 95.0, "value": 120.0}]}},
+                    {"key_as_string": "2025-01-01T10:30:00Z", "doc_count": 3, "m1": {"values": [{"key": 95.0, "value": 80.0}]}}
+                ]}
+            }]}
+        }))
+        .unwrap();
+
+        let (rows, interval) = series(None).rows(&aggregations);
+        assert_eq!(Some("30m".to_string()), interval);
+        assert_eq!(
+            vec![
+                json!(["time", "host.name", "count", "p95(duration)"]),
+                json!(["2025-01-01T10:00:00Z", "web-1", 2, 120.0]),
+                json!(["2025-01-01T10:30:00Z", "web-1", 3, 80.0]),
+            ],
+            rows
+        );
+    }
+
+    #[test]
+    fn change_points() {
+        let detection = ChangeDetection {
+            metric: Metric::count(),
+            split_by: Some("service.name".to_string()),
+            split_size: DEFAULT_CHANGE_SPLIT_SIZE,
+        };
+        let aggregations: IndexMap<String, Value> = serde_json::from_value(json!({
+            "split": {"buckets": [
+                {
+                    "key": "frontend",
+                    "series": {"buckets": [
+                        {"key_as_string": "2025-01-01T13:50:00Z", "doc_count": 10},
+                        {"key_as_string": "2025-01-01T14:00:00Z", "doc_count": 250}
+                    ]},
+                    "change": {
+                        "bucket": {"key_as_string": "2025-01-01T14:00:00Z", "doc_count": 250},
+                        "type": {"spike": {"p_value": 0.0001, "change_point": 1}}
+                    }
+                },
+                {
+                    "key": "checkout",
+                    "series": {"buckets": []},
+                    "change": {
+                        "bucket": {"key_as_string": "2025-01-01T14:10:00Z", "doc_count": 0},
+                        "type": {"step_change": {"p_value": 0.01, "change_point": 0}}
+                    }
+                },
+                {"key": "cart", "change": {"type": {"stationary": {}}}}
+            ]}
+        }))
+        .unwrap();
+
+        let (changes, unchanged) = detection.changes(&aggregations);
+        assert_eq!(1, unchanged);
+        assert_eq!(
+            vec![
+                json!(["p_value", "change", "time", "service.name", "value", "previous_value"]),
+                json!([0.0001, "spike", "2025-01-01T14:00:00Z", "frontend", 250, 10]),
+                json!([0.01, "step_change", "2025-01-01T14:10:00Z", "checkout", 0, null]),
+            ],
+            detection.rows(changes)
+        );
+    }
+}
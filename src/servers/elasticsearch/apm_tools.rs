@@ -0,0 +1,1004 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! APM tools, that analyze traces and services from APM transactions and spans.
+
+use crate::servers::elasticsearch::base_tools::{EsBaseTools, Hit, SearchResult, parse_time_range};
+use crate::servers::elasticsearch::read_json;
+use chrono::{DateTime, NaiveDateTime};
+use elasticsearch::SearchParts;
+use indexmap::IndexMap;
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+
+/// Transactions and spans, and APM errors
+const DEFAULT_TRACE_INDICES: &str = "traces-*,logs-apm.error-*";
+
+const DEFAULT_MAX_SPANS: u32 = 1000;
+const MAX_SPANS: u32 = 10_000;
+
+/// Maximum number of lines of a rendered waterfall
+const MAX_WATERFALL_LINES: usize = 300;
+
+/// Number of spans listed as the slowest of the critical path
+const SLOWEST_SPANS: usize = 5;
+
+const DEFAULT_OVERVIEW_TIME_RANGE: &str = "now-1h";
+const DEFAULT_SERVICES: u32 = 10;
+const MAX_SERVICES: u32 = 50;
+const DEFAULT_TRANSACTIONS: u32 = 5;
+const MAX_TRANSACTIONS: u32 = 20;
+
+/// Label of the rows of a whole service
+const ALL_TRANSACTIONS: &str = "(all)";
+
+/// Fields of transactions, spans and errors used to rebuild traces
+const TRACE_FIELDS: &[&str] = &[
+    "processor.event",
+    "transaction.id",
+    "span.id",
+    "parent.id",
+    "transaction.name",
+    "span.name",
+    "service.name",
+    "transaction.duration.us",
+    "span.duration.us",
+    "timestamp.us",
+    "event.outcome",
+    "error.exception.message",
+    "error.log.message",
+];
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct TraceWaterfallParams {
+    /// Id of the trace
+    trace_id: String,
+
+    /// Index pattern of transactions, spans and errors (optional, default: "traces-*,logs-apm.error-*")
+    index_pattern: Option<String>,
+
+    /// Maximum number of spans read (optional, default: 1000, max: 10000)
+    max_spans: Option<u32>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ServiceOverviewParams {
+    /// Index pattern of APM transactions (optional, default: "traces-*")
+    index_pattern: Option<String>,
+
+    /// Time range (e.g., "now-1h", "now-1d", "2024-01-01T00:00:00Z,2024-01-01T23:59:59Z")
+    /// (optional, default: "now-1h")
+    time_range: Option<String>,
+
+    /// Service name to filter by (optional)
+    service_name: Option<String>,
+
+    /// Compare with the previous period of the same duration (optional, default true)
+    compare: Option<bool>,
+
+    /// Maximum number of services, the busiest first (optional, default: 10, max: 50)
+    services: Option<u32>,
+
+    /// Maximum number of transaction names per service, the busiest first (optional, default: 5, max: 20)
+    transactions: Option<u32>,
+}
+
+#[tool_router(router = apm_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: trace waterfall
+    #[tool(
+        description = "Rebuild a distributed trace from its transactions and spans, and render it as an indented \
+            waterfall with start offsets, durations and errors. Also returns the critical path of the trace, \
+            with the slowest spans and the services that own them.",
+        annotations(title = "APM trace waterfall", read_only_hint = true)
+    )]
+    async fn trace_waterfall(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(TraceWaterfallParams {
+            trace_id,
+            index_pattern,
+            max_spans,
+        }): Parameters<TraceWaterfallParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let index_pattern = index_pattern.as_deref().unwrap_or(DEFAULT_TRACE_INDICES);
+        let max_spans = max_spans.unwrap_or(DEFAULT_MAX_SPANS).clamp(1, MAX_SPANS);
+
+        let response = es_client
+            .search(SearchParts::Index(&[index_pattern]))
+            .body(trace_request(&trace_id, max_spans))
+            .send()
+            .await;
+        let response: SearchResult = read_json(response).await?;
+
+        let total = response.hits.total.as_ref().map_or(0, |t| t.value);
+        let trace = Trace::new(&response.hits.hits);
+        if trace.spans.is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "No transactions or spans found for trace [{trace_id}] in {index_pattern}."
+            ))]));
+        }
+
+        let mut results = vec![Content::text(format!(
+            "Trace {trace_id}: {} spans from {} services, {}{}.",
+            trace.spans.len(),
+            trace.services().len(),
+            format_micros(trace.duration()),
+            match trace.error_count() {
+                0 => String::new(),
+                n => format!(", {n} spans with errors"),
+            }
+        ))];
+        if total > response.hits.hits.len() as u64 {
+            results.push(Content::text(format!(
+                "The trace has {total} documents, only the first {max_spans} were read."
+            )));
+        }
+        if trace.orphan_errors > 0 {
+            results.push(Content::text(format!(
+                "{} errors aren't attached to a span of the trace.",
+                trace.orphan_errors
+            )));
+        }
+
+        results.push(Content::text(trace.render(MAX_WATERFALL_LINES).join("\n")));
+        results.push(Content::text(trace.critical_path_summary()));
+
+        Ok(CallToolResult::success(results))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: service overview
+    #[tool(
+        description = "Throughput, error rate and p50/p95/p99 latency of APM services and their transactions, \
+            compared with the previous period. Returns a table ranked by regression, the worst first, with the \
+            column names in the first row.",
+        annotations(title = "APM service overview", read_only_hint = true)
+    )]
+    async fn service_overview(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(ServiceOverviewParams {
+            index_pattern,
+            time_range,
+            service_name,
+            compare,
+            services,
+            transactions,
+        }): Parameters<ServiceOverviewParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let index_pattern = index_pattern.as_deref().unwrap_or("traces-*");
+        let time_range = time_range.as_deref().unwrap_or(DEFAULT_OVERVIEW_TIME_RANGE);
+        let (start, end) = parse_time_range(time_range)?;
+
+        let seconds = period_seconds(&start, &end);
+        let previous_start = match seconds {
+            Some(seconds) if compare.unwrap_or(true) => Some(previous_period_start(&start, seconds)),
+            _ => None,
+        };
+
+        let overview = ServiceOverview {
+            start,
+            end,
+            previous_start,
+            service_name,
+            services: services.unwrap_or(DEFAULT_SERVICES).clamp(1, MAX_SERVICES),
+            transactions: transactions.unwrap_or(DEFAULT_TRANSACTIONS).clamp(1, MAX_TRANSACTIONS),
+        };
+
+        let response = es_client
+            .search(SearchParts::Index(&[index_pattern]))
+            .body(overview.request())
+            .send()
+            .await;
+        let response: SearchResult = read_json(response).await?;
+
+        let rows = overview.rows(&response.aggregations);
+        if rows.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "No APM transactions in {index_pattern} between {} and {}.",
+                overview.start, overview.end
+            ))]));
+        }
+
+        let mut results = vec![Content::text(format!(
+            "Services and transactions of {index_pattern} between {} and {}{}:",
+            overview.start,
+            overview.end,
+            match &overview.previous_start {
+                Some(previous_start) => format!(
+                    ", compared with {previous_start} to {}, ranked by regression (the largest of the p95 \
+                    change in % and of the error rate change in percentage points)",
+                    overview.start
+                ),
+                None => ", by throughput".to_string(),
+            }
+        ))];
+        self.push_rows("service_overview", ServiceRow::table(rows, seconds), &mut results)?;
+        if seconds.is_none() {
+            results.push(Content::text(
+                "The duration of the time range is unknown: throughput is a count, and there's no comparison.",
+            ));
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+}
+
+fn trace_request(trace_id: &str, max_spans: u32) -> Value {
+    json!({
+        "size": max_spans,
+        "track_total_hits": true,
+        "_source": false,
+        "fields": TRACE_FIELDS,
+        "query": { "term": { "trace.id": trace_id } },
+        "sort": [{ "timestamp.us": { "order": "asc", "unmapped_type": "long" } }]
+    })
+}
+
+//-------------------------------------------------------------------------------------------------
+// Trace
+
+/// A transaction or span.
+#[derive(Debug)]
+struct Span {
+    id: String,
+    parent: Option<String>,
+    name: String,
+    service: String,
+    /// `transaction` or `span`
+    kind: String,
+    start: f64,
+    duration: f64,
+    failed: bool,
+    errors: Vec<String>,
+}
+
+impl Span {
+    fn end(&self) -> f64 {
+        self.start + self.duration
+    }
+}
+
+/// The spans of a trace, as a tree.
+struct Trace {
+    spans: Vec<Span>,
+    /// Indices of the children of each span, by start time
+    children: Vec<Vec<usize>>,
+    /// Indices of the spans without a known parent, by start time
+    roots: Vec<usize>,
+    /// Number of errors whose parent isn't in the trace
+    orphan_errors: usize,
+}
+
+/// First value of a field returned by the `fields` option of a search.
+fn field<'a>(fields: &'a Map<String, Value>, name: &str) -> Option<&'a Value> {
+    fields.get(name).and_then(|values| values.get(0))
+}
+
+fn str_field(fields: &Map<String, Value>, name: &str) -> Option<String> {
+    field(fields, name).and_then(Value::as_str).map(str::to_string)
+}
+
+fn num_field(fields: &Map<String, Value>, name: &str) -> Option<f64> {
+    field(fields, name).and_then(Value::as_f64)
+}
+
+impl Trace {
+    fn new(hits: &[Hit]) -> Trace {
+        let mut spans = Vec::new();
+        let mut errors = Vec::new();
+
+        for fields in hits.iter().filter_map(|hit| hit.fields.as_ref()) {
+            let event = str_field(fields, "processor.event").unwrap_or_else(|| "span".to_string());
+            if event == "error" {
+                let message = str_field(fields, "error.exception.message")
+                    .or_else(|| str_field(fields, "error.log.message"))
+                    .unwrap_or_else(|| "error".to_string());
+                errors.push((str_field(fields, "parent.id"), message));
+                continue;
+            }
+
+            let is_transaction = event == "transaction";
+            let (id, name, duration) = if is_transaction {
+                ("transaction.id", "transaction.name", "transaction.duration.us")
+            } else {
+                ("span.id", "span.name", "span.duration.us")
+            };
+            let Some(id) = str_field(fields, id).or_else(|| str_field(fields, "span.id")) else {
+                continue;
+            };
+
+            spans.push(Span {
+                id,
+                parent: str_field(fields, "parent.id"),
+                name: str_field(fields, name).unwrap_or_default(),
+                service: str_field(fields, "service.name").unwrap_or_default(),
+                kind: event,
+                start: num_field(fields, "timestamp.us").unwrap_or_default(),
+                duration: num_field(fields, duration).unwrap_or_default(),
+                failed: str_field(fields, "event.outcome").as_deref() == Some("failure"),
+                errors: Vec::new(),
+            });
+        }
+
+        let ids: HashMap<String, usize> = spans.iter().enumerate().map(|(i, s)| (s.id.clone(), i)).collect();
+
+        let mut orphan_errors = 0;
+        for (parent, message) in errors {
+            match parent.and_then(|p| ids.get(&p)) {
+                Some(&i) => spans[i].errors.push(message),
+                None => orphan_errors += 1,
+            }
+        }
+
+        let mut children = vec![Vec::new(); spans.len()];
+        let mut roots = Vec::new();
+        for (i, span) in spans.iter().enumerate() {
+            match span.parent.as_ref().and_then(|p| ids.get(p)) {
+                Some(&parent) if parent != i => children[parent].push(i),
+                _ => roots.push(i),
+            }
+        }
+        let by_start = |a: &usize, b: &usize| spans[*a].start.total_cmp(&spans[*b].start);
+        children.iter_mut().for_each(|c| c.sort_by(by_start));
+        roots.sort_by(by_start);
+
+        Trace {
+            spans,
+            children,
+            roots,
+            orphan_errors,
+        }
+    }
+
+    fn start(&self) -> f64 {
+        self.spans.iter().map(|s| s.start).fold(f64::INFINITY, f64::min)
+    }
+
+    fn duration(&self) -> f64 {
+        let end = self.spans.iter().map(Span::end).fold(f64::NEG_INFINITY, f64::max);
+        (end - self.start()).max(0.0)
+    }
+
+    fn services(&self) -> Vec<&str> {
+        let mut services = self.spans.iter().map(|s| s.service.as_str()).collect::<Vec<_>>();
+        services.sort();
+        services.dedup();
+        services
+    }
+
+    fn error_count(&self) -> usize {
+        self.spans.iter().filter(|s| s.failed || !s.errors.is_empty()).count()
+    }
+
+    /// Render the trace as a waterfall: a line per span with its start offset and duration,
+    /// indented by depth.
+    fn render(&self, max_lines: usize) -> Vec<String> {
+        let start = self.start();
+        let mut lines = vec![format!("{:>10} {:>10}  span [service]", "offset", "duration")];
+
+        let mut stack = self.roots.iter().rev().map(|&i| (i, 0)).collect::<Vec<_>>();
+        while let Some((i, depth)) = stack.pop() {
+            let span = &self.spans[i];
+            let mut line = format!(
+                "{:>10} {:>10}  {}{} [{}]",
+                format_micros(span.start - start),
+                format_micros(span.duration),
+                "  ".repeat(depth),
+                span.name,
+                span.service
+            );
+            if span.kind == "transaction" {
+                line.push_str(" (transaction)");
+            }
+            if span.failed {
+                line.push_str(" FAILED");
+            }
+            for error in &span.errors {
+                line.push_str(&format!(" ERROR: {error}"));
+            }
+            lines.push(line);
+
+            stack.extend(self.children[i].iter().rev().map(|&c| (c, depth + 1)));
+        }
+
+        let total = lines.len();
+        lines.truncate(max_lines);
+        if total > lines.len() {
+            lines.push(format!("… [{} more spans]", total - lines.len()));
+        }
+        lines
+    }
+
+    /// The critical path of the trace: the spans that the trace was waiting for, with the time
+    /// they contributed.
+    fn critical_path(&self) -> Vec<(usize, f64)> {
+        // The longest root, if the trace is fragmented
+        let root = self
+            .roots
+            .iter()
+            .max_by(|a, b| self.spans[**a].duration.total_cmp(&self.spans[**b].duration));
+        let mut path = Vec::new();
+        if let Some(&root) = root {
+            let span = &self.spans[root];
+            self.walk_critical_path(root, (span.start, span.end().max(span.start)), &mut path);
+        }
+        path.reverse();
+        path
+    }
+
+    /// Walk back from the end of a span, within `interval`: the last child that finished is on the
+    /// critical path, then the last one that finished before it started, and so on. The gaps are
+    /// the span's own time.
+    ///
+    /// Children are clamped into the interval, as clock skew between services can make them start
+    /// before or end after their parent. Since the cursor moves back to the (clamped) start of each
+    /// child, it strictly decreases and every child is visited at most once.
+    fn walk_critical_path(&self, i: usize, (from, to): (f64, f64), path: &mut Vec<(usize, f64)>) {
+        let mut cursor = to;
+        let mut self_time = 0.0;
+        let mut segments = Vec::new();
+
+        let start = |c: usize| self.spans[c].start.clamp(from, to);
+        loop {
+            let last = self.children[i]
+                .iter()
+                .filter(|&&c| start(c) < cursor)
+                .max_by(|&&a, &&b| self.spans[a].end().total_cmp(&self.spans[b].end()));
+            let Some(&child) = last else { break };
+
+            let child_start = start(child);
+            let child_end = self.spans[child].end().clamp(child_start, cursor);
+            self_time += cursor - child_end;
+            let mut child_path = Vec::new();
+            self.walk_critical_path(child, (child_start, child_end), &mut child_path);
+            segments.push(child_path);
+            cursor = child_start;
+        }
+        self_time += cursor - from;
+
+        // Spans are pushed from the end of the trace, the path is reversed by the caller
+        for segment in segments {
+            path.extend(segment);
+        }
+        path.push((i, self_time));
+    }
+
+    /// Time spent on the critical path by service, and the slowest spans of the critical path.
+    fn critical_path_summary(&self) -> String {
+        let path = self.critical_path();
+        let total: f64 = path.iter().map(|(_, time)| time).sum();
+        if total <= 0.0 {
+            return "Critical path: no span durations.".to_string();
+        }
+
+        let mut by_service: Vec<(&str, f64)> = Vec::new();
+        for (i, time) in &path {
+            let service = self.spans[*i].service.as_str();
+            match by_service.iter_mut().find(|(s, _)| *s == service) {
+                Some((_, t)) => *t += time,
+                None => by_service.push((service, *time)),
+            }
+        }
+        by_service.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let by_service = by_service
+            .iter()
+            .map(|(service, time)| format!("{service} {} ({:.0}%)", format_micros(*time), time / total * 100.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut slowest = path.clone();
+        slowest.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let slowest = slowest
+            .iter()
+            .take(SLOWEST_SPANS)
+            .filter(|(_, time)| *time > 0.0)
+            .map(|(i, time)| {
+                let span = &self.spans[*i];
+                format!("- {} [{}]: {}", span.name, span.service, format_micros(*time))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!(
+            "Critical path: {} in {} spans. Time by service: {by_service}.\nSlowest spans of the critical path \
+            (own time):\n{slowest}",
+            format_micros(total),
+            path.len()
+        )
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Service overview
+
+/// Duration of a time range in seconds, for relative ranges like `now-1h` to `now` and for
+/// RFC 3339 timestamps.
+fn period_seconds(start: &str, end: &str) -> Option<u64> {
+    if end == "now"
+        && let Some(amount) = start.strip_prefix("now-")
+    {
+        let unit = amount.chars().last()?;
+        let amount = amount[..amount.len() - unit.len_utf8()].parse::<u64>().ok()?;
+        let unit_seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 7 * 86400,
+            'M' => 30 * 86400,
+            'y' => 365 * 86400,
+            _ => return None,
+        };
+        return amount.checked_mul(unit_seconds);
+    }
+
+    let seconds = epoch_seconds(end)?.checked_sub(epoch_seconds(start)?)?;
+    u64::try_from(seconds).ok().filter(|seconds| *seconds > 0)
+}
+
+/// Seconds since the Unix epoch of an RFC 3339 timestamp. Timestamps without an offset are UTC,
+/// like in Elasticsearch range queries.
+fn epoch_seconds(timestamp: &str) -> Option<i64> {
+    let timestamp = timestamp.trim();
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| time.timestamp())
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f").map(|time| time.and_utc().timestamp())
+        })
+        .ok()
+}
+
+/// Start of the period of `seconds` that ends at `start`, as date math.
+fn previous_period_start(start: &str, seconds: u64) -> String {
+    if start.starts_with("now") {
+        format!("{start}-{seconds}s")
+    } else {
+        format!("{start}||-{seconds}s")
+    }
+}
+
+struct ServiceOverview {
+    start: String,
+    end: String,
+    /// Start of the previous period, which ends at `start`
+    previous_start: Option<String>,
+    service_name: Option<String>,
+    services: u32,
+    transactions: u32,
+}
+
+/// Transaction statistics of a service or transaction name.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct TransactionStats {
+    count: u64,
+    failures: u64,
+    /// Latency percentiles in microseconds
+    p50: Option<f64>,
+    p95: Option<f64>,
+    p99: Option<f64>,
+}
+
+#[derive(Debug, PartialEq)]
+struct ServiceRow {
+    service: String,
+    transaction: String,
+    current: TransactionStats,
+    previous: Option<TransactionStats>,
+}
+
+impl ServiceOverview {
+    fn request(&self) -> Value {
+        let range = |gte: &str, lt: &str| json!({ "range": { "@timestamp": { "gte": gte, "lt": lt } } });
+
+        let mut filters = vec![
+            json!({ "term": { "processor.event": "transaction" } }),
+            json!({ "range": { "@timestamp": {
+                "gte": self.previous_start.as_deref().unwrap_or(&self.start),
+                "lte": self.end
+            } } }),
+        ];
+        if let Some(service_name) = &self.service_name {
+            filters.push(json!({ "term": { "service.name": service_name } }));
+        }
+
+        let mut periods = Map::new();
+        periods.insert(
+            "current".to_string(),
+            json!({ "range": { "@timestamp": { "gte": self.start, "lte": self.end } } }),
+        );
+        if let Some(previous_start) = &self.previous_start {
+            periods.insert("previous".to_string(), range(previous_start, &self.start));
+        }
+
+        let stats = json!({
+            "latency": { "percentiles": { "field": "transaction.duration.us", "percents": [50, 95, 99] } },
+            "failures": { "filter": { "term": { "event.outcome": "failure" } } }
+        });
+        let mut service_aggs = stats.as_object().cloned().unwrap_or_default();
+        service_aggs.insert(
+            "transactions".to_string(),
+            json!({
+                "terms": { "field": "transaction.name", "size": self.transactions },
+                "aggs": stats
+            }),
+        );
+
+        json!({
+            "size": 0,
+            "query": { "bool": { "filter": filters } },
+            "aggs": {
+                "periods": {
+                    "filters": { "filters": periods },
+                    "aggs": {
+                        "services": {
+                            "terms": { "field": "service.name", "size": self.services },
+                            "aggs": service_aggs
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Rows of the services and their transactions, ranked by regression, or by throughput
+    /// without a previous period.
+    fn rows(&self, aggregations: &IndexMap<String, Value>) -> Vec<ServiceRow> {
+        let periods = aggregations.get("periods").and_then(|p| p.get("buckets"));
+        let stats = |period: &str| {
+            let mut stats = Vec::new();
+            let services = periods
+                .and_then(|p| p.pointer(&format!("/{period}/services/buckets")))
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten();
+            for service in services {
+                let name = key(service);
+                stats.push((
+                    name.clone(),
+                    ALL_TRANSACTIONS.to_string(),
+                    TransactionStats::new(service),
+                ));
+                let transactions = service
+                    .pointer("/transactions/buckets")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten();
+                for transaction in transactions {
+                    stats.push((name.clone(), key(transaction), TransactionStats::new(transaction)));
+                }
+            }
+            stats
+        };
+
+        let mut previous: HashMap<(String, String), TransactionStats> = HashMap::new();
+        if self.previous_start.is_some() {
+            previous.extend(stats("previous").into_iter().map(|(s, t, stats)| ((s, t), stats)));
+        }
+
+        let mut rows = stats("current")
+            .into_iter()
+            .map(|(service, transaction, current)| {
+                let previous = previous.remove(&(service.clone(), transaction.clone()));
+                ServiceRow {
+                    service,
+                    transaction,
+                    current,
+                    previous,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        rows.sort_by(|a, b| {
+            let regression = |row: &ServiceRow| row.regression().unwrap_or(f64::NEG_INFINITY);
+            regression(b)
+                .total_cmp(&regression(a))
+                .then(b.current.count.cmp(&a.current.count))
+        });
+        rows
+    }
+}
+
+fn key(bucket: &Value) -> String {
+    match bucket.get("key") {
+        Some(Value::String(key)) => key.clone(),
+        Some(key) => key.to_string(),
+        None => String::new(),
+    }
+}
+
+impl TransactionStats {
+    fn new(bucket: &Value) -> TransactionStats {
+        let percentile = |p: &str| bucket.pointer(&format!("/latency/values/{p}")).and_then(Value::as_f64);
+        TransactionStats {
+            count: bucket.get("doc_count").and_then(Value::as_u64).unwrap_or_default(),
+            failures: bucket
+                .pointer("/failures/doc_count")
+                .and_then(Value::as_u64)
+                .unwrap_or_default(),
+            p50: percentile("50.0"),
+            p95: percentile("95.0"),
+            p99: percentile("99.0"),
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            count => self.failures as f64 / count as f64,
+        }
+    }
+}
+
+impl ServiceRow {
+    /// Relative change of the p95 latency, in percent.
+    fn p95_change(&self) -> Option<f64> {
+        let previous = self.previous.as_ref()?.p95.filter(|p95| *p95 > 0.0)?;
+        Some((self.current.p95? - previous) / previous * 100.0)
+    }
+
+    /// Change of the error rate, in percentage points.
+    fn error_rate_change(&self) -> Option<f64> {
+        let previous = self.previous.as_ref()?;
+        Some((self.current.error_rate() - previous.error_rate()) * 100.0)
+    }
+
+    /// How much worse the row is than in the previous period.
+    fn regression(&self) -> Option<f64> {
+        match (self.p95_change(), self.error_rate_change()) {
+            (Some(latency), Some(errors)) => Some(latency.max(errors)),
+            (latency, errors) => latency.or(errors),
+        }
+    }
+
+    /// Table of rows, with the column names in the first row. Throughput is per minute if the
+    /// duration of the period is known.
+    fn table(rows: Vec<ServiceRow>, seconds: Option<u64>) -> Vec<Value> {
+        let round = |value: Option<f64>| value.map(|v| (v * 10.0).round() / 10.0);
+        let millis = |micros: Option<f64>| round(micros.map(|us| us / 1000.0));
+        let throughput = if seconds.is_some() {
+            "throughput_per_min"
+        } else {
+            "count"
+        };
+
+        let mut table = vec![json!([
+            "service",
+            "transaction",
+            throughput,
+            "error_rate_pct",
+            "p50_ms",
+            "p95_ms",
+            "p99_ms",
+            "p95_change_pct",
+            "error_rate_change_pp"
+        ])];
+        for row in rows {
+            let throughput = match seconds {
+                Some(seconds) => json!(round(Some(row.current.count as f64 * 60.0 / seconds as f64))),
+                None => json!(row.current.count),
+            };
+            table.push(json!([
+                row.service,
+                row.transaction,
+                throughput,
+                round(Some(row.current.error_rate() * 100.0)),
+                millis(row.current.p50),
+                millis(row.current.p95),
+                millis(row.current.p99),
+                round(row.p95_change()),
+                round(row.error_rate_change()),
+            ]));
+        }
+        table
+    }
+}
+
+/// Format a duration in microseconds, e.g. `12.3ms` or `1.5s`.
+fn format_micros(micros: f64) -> String {
+    let millis = micros / 1000.0;
+    if millis < 1000.0 {
+        format!("{millis:.1}ms")
+    } else {
+        format!("{:.2}s", millis / 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(fields: Value) -> Hit {
+        let fields = fields
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), json!([v])))
+            .collect::<Map<_, _>>();
+        serde_json::from_value(json!({ "fields": fields })).unwrap()
+    }
+
+    fn trace() -> Trace {
+        Trace::new(&[
+            hit(json!({
+                "processor.event": "transaction", "transaction.id": "t1", "transaction.name": "GET /checkout",
+                "service.name": "frontend", "timestamp.us": 0, "transaction.duration.us": 100_000
+            })),
+            hit(json!({
+                "processor.event": "span", "span.id": "s1", "parent.id": "t1", "span.name": "POST /cart",
+                "service.name": "frontend", "timestamp.us": 10_000, "span.duration.us": 30_000
+            })),
+            hit(json!({
+                "processor.event": "span", "span.id": "s2", "parent.id": "t1", "span.name": "SELECT orders",
+                "service.name": "frontend", "timestamp.us": 20_000, "span.duration.us": 70_000,
+                "event.outcome": "failure"
+            })),
+            hit(json!({
+                "processor.event": "transaction", "transaction.id": "t2", "parent.id": "s1",
+                "transaction.name": "POST /cart", "service.name": "cart", "timestamp.us": 12_000,
+                "transaction.duration.us": 25_000
+            })),
+            hit(json!({
+                "processor.event": "error", "parent.id": "s2", "error.exception.message": "timeout"
+            })),
+        ])
+    }
+
+    #[test]
+    fn waterfall() {
+        let trace = trace();
+        assert_eq!(1, trace.error_count());
+        assert_eq!(vec!["cart", "frontend"], trace.services());
+        assert_eq!(
+            vec![
+                "    offset   duration  span [service]",
+                "     0.0ms    100.0ms  GET /checkout [frontend] (transaction)",
+                "    10.0ms     30.0ms    POST /cart [frontend]",
+                "    12.0ms     25.0ms      POST /cart [cart] (transaction)",
+                "    20.0ms     70.0ms    SELECT orders [frontend] FAILED ERROR: timeout",
+            ],
+            trace.render(MAX_WATERFALL_LINES)
+        );
+        assert_eq!(3, trace.render(2).len());
+    }
+
+    #[test]
+    fn critical_path() {
+        let trace = trace();
+        let path = trace
+            .critical_path()
+            .into_iter()
+            .map(|(i, time)| (trace.spans[i].id.as_str(), time))
+            .collect::<Vec<_>>();
+
+        // The root waits for s2 to finish, and before s2 started for s1, which waits for t2
+        assert_eq!(
+            vec![("t1", 20_000.0), ("s1", 2_000.0), ("t2", 8_000.0), ("s2", 70_000.0)],
+            path
+        );
+    }
+
+    #[test]
+    fn critical_path_with_clock_skew() {
+        // The child's clock is 2ms behind: it starts before its parent, and ends after it
+        let trace = Trace::new(&[
+            hit(json!({
+                "processor.event": "transaction", "transaction.id": "t1", "transaction.name": "GET /",
+                "service.name": "frontend", "timestamp.us": 10_000, "transaction.duration.us": 20_000
+            })),
+            hit(json!({
+                "processor.event": "span", "span.id": "s1", "parent.id": "t1", "span.name": "GET /api",
+                "service.name": "frontend", "timestamp.us": 8_000, "span.duration.us": 5_000
+            })),
+            hit(json!({
+                "processor.event": "transaction", "transaction.id": "t2", "parent.id": "t1",
+                "transaction.name": "GET /api", "service.name": "api", "timestamp.us": 25_000,
+                "transaction.duration.us": 10_000
+            })),
+        ]);
+        let path = trace
+            .critical_path()
+            .into_iter()
+            .map(|(i, time)| (trace.spans[i].id.as_str(), time))
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![("t1", 12_000.0), ("s1", 3_000.0), ("t2", 5_000.0)], path);
+    }
+
+    #[test]
+    fn periods() {
+        assert_eq!(Some(3600), period_seconds("now-1h", "now"));
+        assert_eq!(Some(7 * 86400), period_seconds("now-7d", "now"));
+        assert_eq!(
+            Some(86400),
+            period_seconds("2024-01-01T00:00:00Z", "2024-01-02T00:00:00Z")
+        );
+        assert_eq!(
+            Some(3600),
+            period_seconds("2024-01-01T10:00:00+02:00", "2024-01-01T09:00:00.000Z")
+        );
+        assert_eq!(
+            Some(1800),
+            period_seconds("2024-01-01T00:00:00", "2024-01-01T00:30:00Z")
+        );
+        assert_eq!(None, period_seconds("now-1h", "now-5m"));
+        assert_eq!(None, period_seconds(&format!("now-{}y", u64::MAX), "now"));
+        assert_eq!(None, period_seconds("2024-01-02T00:00:00Z", "2024-01-01T00:00:00Z"));
+
+        assert_eq!("now-1h-3600s", previous_period_start("now-1h", 3600));
+        assert_eq!(
+            "2024-01-01T00:00:00Z||-86400s",
+            previous_period_start("2024-01-01T00:00:00Z", 86400)
+        );
+    }
+
+    #[test]
+    fn service_overview() {
+        let overview = ServiceOverview {
+            start: "now-1h".to_string(),
+            end: "now".to_string(),
+            previous_start: Some("now-1h-3600s".to_string()),
+            service_name: None,
+            services: DEFAULT_SERVICES,
+            transactions: DEFAULT_TRANSACTIONS,
+        };
+        let service = |name: &str, count: u64, failures: u64, p95: f64| {
+            json!({
+                "key": name,
+                "doc_count": count,
+                "failures": {"doc_count": failures},
+                "latency": {"values": {"50.0": p95 / 2.0, "95.0": p95, "99.0": p95 * 2.0}},
+                "transactions": {"buckets": []}
+            })
+        };
+        let aggregations: IndexMap<String, Value> = serde_json::from_value(json!({
+            "periods": {"buckets": {
+                "current": {"services": {"buckets": [
+                    service("frontend", 600, 6, 100_000.0),
+                    service("checkout", 60, 30, 50_000.0),
+                ]}},
+                "previous": {"services": {"buckets": [
+                    service("frontend", 600, 6, 50_000.0),
+                    service("checkout", 60, 0, 50_000.0),
+                ]}}
+            }}
+        }))
+        .unwrap();
+
+        let rows = overview.rows(&aggregations);
+        // frontend's p95 doubled (+100%), checkout's error rate went from 0 to 50% (+50pp)
+        assert_eq!(
+            vec!["frontend", "checkout"],
+            rows.iter().map(|r| r.service.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            json!(["frontend", "(all)", 10.0, 1.0, 50.0, 100.0, 200.0, 100.0, 0.0]),
+            ServiceRow::table(rows, Some(3600))[1]
+        );
+    }
+}
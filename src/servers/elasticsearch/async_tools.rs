@@ -0,0 +1,494 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Async ES|QL and async search tools, for long-running queries.
+//!
+//! Queries are submitted with a `wait_for_completion_timeout`: if they don't complete in time,
+//! the tool returns an id and partial results, and the caller polls with the `*_get` tools.
+//! If the client provides a progress token, the tools poll Elasticsearch while waiting and send
+//! progress notifications.
+
+use crate::servers::elasticsearch::base_tools::{EsBaseTools, EsqlOptions, EsqlQueryResponse, SearchResult};
+use crate::servers::elasticsearch::pagination::KEEP_ALIVE;
+use crate::servers::elasticsearch::{read_json, read_json_opt};
+use elasticsearch::Elasticsearch;
+use elasticsearch::async_search::{AsyncSearchDeleteParts, AsyncSearchGetParts, AsyncSearchSubmitParts};
+use elasticsearch::esql::{EsqlAsyncQueryDeleteParts, EsqlAsyncQueryGetParts};
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content, ProgressNotificationParam, ProgressToken};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::{Duration, Instant};
+
+/// Default time to wait for a query to complete, in seconds
+const DEFAULT_WAIT_SECONDS: u64 = 10;
+
+/// Interval between two polls when sending progress notifications
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct EsqlAsyncSubmitParams {
+    /// Complete Elasticsearch ES|QL query
+    query: String,
+
+    #[serde(flatten)]
+    options: EsqlOptions,
+
+    /// Seconds to wait for the query to complete before returning its id (default: 10)
+    wait_seconds: Option<u64>,
+
+    /// How long to keep the query and its results after the last call (default: "5m")
+    keep_alive: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct AsyncSearchSubmitParams {
+    /// Name of the Elasticsearch index to search
+    index: String,
+
+    /// Complete Elasticsearch query DSL object that can include query, size, from, sort, aggs, etc.
+    query_body: Map<String, Value>,
+
+    /// Seconds to wait for the search to complete before returning its id (default: 10)
+    wait_seconds: Option<u64>,
+
+    /// How long to keep the search and its results after the last call (default: "5m")
+    keep_alive: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct AsyncGetParams {
+    /// Id returned when the query was submitted
+    id: String,
+
+    /// Seconds to wait for the query to complete before returning (default: 10)
+    wait_seconds: Option<u64>,
+
+    /// How long to keep the query and its results after this call (default: "5m")
+    keep_alive: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct AsyncDeleteParams {
+    /// Id returned when the query was submitted
+    id: String,
+}
+
+#[tool_router(router = async_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: submit an async ES|QL query
+    #[tool(
+        description = "Submit a long-running ES|QL query. Returns the results if the query completes within \
+            `wait_seconds`, otherwise its id and partial results: use `esql_async_get` to get its results.",
+        annotations(title = "Submit async ES|QL query", read_only_hint = true)
+    )]
+    async fn esql_async_submit(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(EsqlAsyncSubmitParams {
+            query,
+            options,
+            wait_seconds,
+            keep_alive,
+        }): Parameters<EsqlAsyncSubmitParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let mut poller = Poller::new(&req_ctx, wait_seconds);
+        let es_client = self.es_client.get(req_ctx);
+        let keep_alive = keep_alive.unwrap_or_else(|| KEEP_ALIVE.to_string());
+
+        let mut request = options.into_request(query)?;
+        request.wait_for_completion_timeout = Some(poller.timeout());
+        request.keep_alive = Some(keep_alive.clone());
+
+        let response = es_client.esql().async_query().body(request).send().await;
+        let response: EsqlQueryResponse = read_json(response).await?;
+
+        let response = self.poll_esql(&es_client, &mut poller, response, &keep_alive).await?;
+        self.esql_async_result("esql_async_submit", response)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: get the results of an async ES|QL query
+    #[tool(
+        description = "Get the results of an ES|QL query submitted with `esql_async_submit`, waiting up to \
+            `wait_seconds` for it to complete.",
+        annotations(title = "Get async ES|QL results", read_only_hint = true)
+    )]
+    async fn esql_async_get(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(AsyncGetParams {
+            id,
+            wait_seconds,
+            keep_alive,
+        }): Parameters<AsyncGetParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let mut poller = Poller::new(&req_ctx, wait_seconds);
+        let es_client = self.es_client.get(req_ctx);
+        let keep_alive = keep_alive.unwrap_or_else(|| KEEP_ALIVE.to_string());
+
+        let Some(response) = esql_get(&es_client, &id, &poller.timeout(), &keep_alive).await? else {
+            return Ok(not_found("ES|QL query", &id));
+        };
+
+        let response = self.poll_esql(&es_client, &mut poller, response, &keep_alive).await?;
+        self.esql_async_result("esql_async_get", response)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: delete an async ES|QL query
+    #[tool(
+        description = "Delete an ES|QL query submitted with `esql_async_submit` and its results. \
+            The query is cancelled if it's still running.",
+        annotations(title = "Delete async ES|QL query", read_only_hint = false, destructive_hint = false)
+    )]
+    async fn esql_async_delete(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(AsyncDeleteParams { id }): Parameters<AsyncDeleteParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let response = es_client
+            .esql()
+            .async_query_delete(EsqlAsyncQueryDeleteParts::Id(&id))
+            .send()
+            .await;
+
+        match read_json_opt::<Value>(response).await? {
+            Some(_) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "ES|QL query [{id}] deleted."
+            ))])),
+            None => Ok(not_found("ES|QL query", &id)),
+        }
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: submit an async search
+    #[tool(
+        description = "Submit a long-running Elasticsearch search. Returns the results if the search completes within \
+            `wait_seconds`, otherwise its id and partial results: use `async_search_get` to get its results.",
+        annotations(title = "Submit async search", read_only_hint = true)
+    )]
+    async fn async_search_submit(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(AsyncSearchSubmitParams {
+            index,
+            query_body,
+            wait_seconds,
+            keep_alive,
+        }): Parameters<AsyncSearchSubmitParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let mut poller = Poller::new(&req_ctx, wait_seconds);
+        let es_client = self.es_client.get(req_ctx);
+        let keep_alive = keep_alive.unwrap_or_else(|| KEEP_ALIVE.to_string());
+
+        let response = es_client
+            .async_search()
+            .submit(AsyncSearchSubmitParts::Index(&[&index]))
+            .wait_for_completion_timeout(&poller.timeout())
+            .keep_alive(&keep_alive)
+            .body(query_body)
+            .send()
+            .await;
+        let response: AsyncSearchResponse = read_json(response).await?;
+
+        let response = poll_search(&es_client, &mut poller, response, &keep_alive).await?;
+        self.async_search_result("async_search_submit", response)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: get the results of an async search
+    #[tool(
+        description = "Get the results of a search submitted with `async_search_submit`, waiting up to \
+            `wait_seconds` for it to complete.",
+        annotations(title = "Get async search results", read_only_hint = true)
+    )]
+    async fn async_search_get(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(AsyncGetParams {
+            id,
+            wait_seconds,
+            keep_alive,
+        }): Parameters<AsyncGetParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let mut poller = Poller::new(&req_ctx, wait_seconds);
+        let es_client = self.es_client.get(req_ctx);
+        let keep_alive = keep_alive.unwrap_or_else(|| KEEP_ALIVE.to_string());
+
+        let Some(response) = search_get(&es_client, &id, &poller.timeout(), &keep_alive).await? else {
+            return Ok(not_found("Search", &id));
+        };
+
+        let response = poll_search(&es_client, &mut poller, response, &keep_alive).await?;
+        self.async_search_result("async_search_get", response)
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: delete an async search
+    #[tool(
+        description = "Delete a search submitted with `async_search_submit` and its results. \
+            The search is cancelled if it's still running.",
+        annotations(title = "Delete async search", read_only_hint = false, destructive_hint = false)
+    )]
+    async fn async_search_delete(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(AsyncDeleteParams { id }): Parameters<AsyncDeleteParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let response = es_client
+            .async_search()
+            .delete(AsyncSearchDeleteParts::Id(&id))
+            .send()
+            .await;
+
+        match read_json_opt::<Value>(response).await? {
+            Some(_) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Search [{id}] deleted."
+            ))])),
+            None => Ok(not_found("Search", &id)),
+        }
+    }
+}
+
+impl EsBaseTools {
+    /// Poll a running ES|QL query until it completes or the poller's deadline is reached.
+    async fn poll_esql(
+        &self,
+        es_client: &Elasticsearch,
+        poller: &mut Poller,
+        mut response: EsqlQueryResponse,
+        keep_alive: &str,
+    ) -> Result<EsqlQueryResponse, rmcp::Error> {
+        while response.is_running
+            && let Some(id) = response.id.clone()
+            && poller.poll(None).await
+        {
+            match esql_get(es_client, &id, &poller.timeout(), keep_alive).await? {
+                Some(next) => response = next,
+                None => break,
+            }
+        }
+        Ok(response)
+    }
+
+    fn esql_async_result(&self, tool: &str, response: EsqlQueryResponse) -> Result<CallToolResult, rmcp::Error> {
+        let mut results = Vec::new();
+        match (&response.id, response.is_running) {
+            (Some(id), true) => results.push(Content::text(format!(
+                "Query [{id}] is still running, showing partial results. \
+                Call `esql_async_get` with this id to get more results."
+            ))),
+            (Some(id), false) => results.push(Content::text(format!(
+                "Query [{id}] is complete. \
+                Its results are kept until they expire or are deleted with `esql_async_delete`."
+            ))),
+            (None, _) => results.push(Content::text("Query is complete.")),
+        }
+
+        self.push_esql_results(tool, response, &mut results)?;
+        Ok(CallToolResult::success(results))
+    }
+
+    fn async_search_result(&self, tool: &str, response: AsyncSearchResponse) -> Result<CallToolResult, rmcp::Error> {
+        let mut results = Vec::new();
+        let search = response.response;
+
+        let total = search
+            .hits
+            .total
+            .as_ref()
+            .map(|t| t.value.to_string())
+            .unwrap_or("unknown".to_string());
+
+        let status = match (&response.id, response.is_running) {
+            (Some(id), true) => format!(
+                "Search [{id}] is still running, showing partial results{}. \
+                Call `async_search_get` with this id to get more results.",
+                shards_progress(&search)
+                    .map(|(done, total)| format!(" from {done} of {total} shards"))
+                    .unwrap_or_default()
+            ),
+            (Some(id), false) => format!(
+                "Search [{id}] is complete. \
+                Its results are kept until they expire or are deleted with `async_search_delete`."
+            ),
+            (None, _) => "Search is complete.".to_string(),
+        };
+        results.push(Content::text(format!(
+            "{status} Total results: {total}, showing {}.",
+            search.hits.hits.len()
+        )));
+
+        if response.is_partial && !response.is_running {
+            results.push(Content::text("Results are partial: some shards failed."));
+        }
+
+        self.push_hits(tool, &search.hits, &mut results)?;
+
+        if !search.aggregations.is_empty() {
+            results.push(Content::text("Aggregations results:"));
+            self.push_aggregations(tool, search.aggregations, &mut results)?;
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+}
+
+/// Poll a running async search until it completes or the poller's deadline is reached.
+async fn poll_search(
+    es_client: &Elasticsearch,
+    poller: &mut Poller,
+    mut response: AsyncSearchResponse,
+    keep_alive: &str,
+) -> Result<AsyncSearchResponse, rmcp::Error> {
+    while response.is_running
+        && let Some(id) = response.id.clone()
+        && poller.poll(shards_progress(&response.response)).await
+    {
+        match search_get(es_client, &id, &poller.timeout(), keep_alive).await? {
+            Some(next) => response = next,
+            None => break,
+        }
+    }
+    Ok(response)
+}
+
+async fn esql_get(
+    es_client: &Elasticsearch,
+    id: &str,
+    timeout: &str,
+    keep_alive: &str,
+) -> Result<Option<EsqlQueryResponse>, rmcp::Error> {
+    let response = es_client
+        .esql()
+        .async_query_get(EsqlAsyncQueryGetParts::Id(id))
+        .wait_for_completion_timeout(timeout)
+        .keep_alive(keep_alive)
+        .send()
+        .await;
+    read_json_opt(response).await
+}
+
+async fn search_get(
+    es_client: &Elasticsearch,
+    id: &str,
+    timeout: &str,
+    keep_alive: &str,
+) -> Result<Option<AsyncSearchResponse>, rmcp::Error> {
+    let response = es_client
+        .async_search()
+        .get(AsyncSearchGetParts::Id(id))
+        .wait_for_completion_timeout(timeout)
+        .keep_alive(keep_alive)
+        .send()
+        .await;
+    read_json_opt(response).await
+}
+
+/// Number of shards that have completed, and total number of shards
+fn shards_progress(search: &SearchResult) -> Option<(u32, u32)> {
+    search
+        .shards
+        .as_ref()
+        .map(|shards| (shards.successful + shards.failed, shards.total))
+}
+
+fn not_found(what: &str, id: &str) -> CallToolResult {
+    CallToolResult::error(vec![Content::text(format!(
+        "{what} [{id}] not found: it may have expired or been deleted."
+    ))])
+}
+
+#[derive(Deserialize)]
+struct AsyncSearchResponse {
+    id: Option<String>,
+    #[serde(default)]
+    is_running: bool,
+    #[serde(default)]
+    is_partial: bool,
+    response: SearchResult,
+}
+
+//-------------------------------------------------------------------------------------------------
+
+/// Waits for an async query to complete within a deadline. If the client provided a progress
+/// token, Elasticsearch is polled at regular intervals and a progress notification is sent after
+/// each poll. Otherwise a single request waits until the deadline.
+struct Poller {
+    context: RequestContext<RoleServer>,
+    progress_token: Option<ProgressToken>,
+    deadline: Instant,
+    polls: u32,
+}
+
+impl Poller {
+    fn new(context: &RequestContext<RoleServer>, wait_seconds: Option<u64>) -> Self {
+        let wait = Duration::from_secs(wait_seconds.unwrap_or(DEFAULT_WAIT_SECONDS));
+        Poller {
+            context: context.clone(),
+            progress_token: context.meta.get_progress_token(),
+            deadline: Instant::now() + wait,
+            polls: 0,
+        }
+    }
+
+    /// The `wait_for_completion_timeout` of the next request to Elasticsearch
+    fn timeout(&self) -> String {
+        let mut timeout = self.deadline.saturating_duration_since(Instant::now());
+        if self.progress_token.is_some() {
+            timeout = timeout.min(POLL_INTERVAL);
+        }
+        format!("{}ms", timeout.as_millis())
+    }
+
+    /// Called when a query is still running: sends a progress notification and returns `true`
+    /// if the query should be polled again. `progress` is the completed and total work, if known.
+    async fn poll(&mut self, progress: Option<(u32, u32)>) -> bool {
+        let Some(progress_token) = self.progress_token.clone() else {
+            return false;
+        };
+        if Instant::now() >= self.deadline || self.context.ct.is_cancelled() {
+            return false;
+        }
+
+        self.polls += 1;
+        let (progress, total) = match progress {
+            Some((done, total)) => (done, Some(total)),
+            None => (self.polls, None),
+        };
+
+        let notification = ProgressNotificationParam {
+            progress_token,
+            progress,
+            total,
+            message: Some("Query is still running".to_string()),
+        };
+        if let Err(err) = self.context.peer.notify_progress(notification).await {
+            tracing::debug!("Failed to send progress notification: {err}");
+        }
+        true
+    }
+}
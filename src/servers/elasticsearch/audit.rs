@@ -0,0 +1,336 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Audit log of tool invocations.
+//!
+//! Records are sent to a background task that writes them to the configured sink, so that
+//! tool calls are not slowed down by audit logging.
+
+use crate::servers::elasticsearch::caller::Caller;
+use crate::servers::elasticsearch::{AuditConfig, AuditSink, FileAuditSink};
+use chrono::{DateTime, SecondsFormat, Utc};
+use elasticsearch::http::response::Response;
+use elasticsearch::{Elasticsearch, IndexParts};
+use rmcp::model::{CallToolRequestParam, CallToolResult};
+use serde::Serialize;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc;
+
+/// Argument names that are always redacted. Only whole names are matched, so that fields of query
+/// bodies like `max_tokens` aren't redacted.
+const SECRET_NAMES: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "client_secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "api_key",
+    "apikey",
+    "authorization",
+];
+
+const REDACTED: &str = "[REDACTED]";
+
+tokio::task_local! {
+    /// Elasticsearch requests sent by the current tool call
+    static ES_REQUESTS: RefCell<Vec<String>>;
+}
+
+/// Run a tool call, collecting the Elasticsearch requests it sends.
+pub async fn track_es_requests<T>(fut: impl Future<Output = T>) -> (T, Vec<String>) {
+    ES_REQUESTS
+        .scope(RefCell::new(Vec::new()), async {
+            let result = fut.await;
+            (result, ES_REQUESTS.with(|r| r.take()))
+        })
+        .await
+}
+
+/// Record an Elasticsearch request for the current tool call, if it is tracked.
+pub fn record_es_request(response: &Response) {
+    let _ = ES_REQUESTS.try_with(|r| {
+        let method = format!("{:?}", response.method()).to_uppercase();
+        r.borrow_mut().push(format!("{method} {}", response.url().path()))
+    });
+}
+
+#[derive(Serialize)]
+pub struct AuditRecord {
+    #[serde(rename = "@timestamp")]
+    pub timestamp: String,
+    pub tool: String,
+    pub arguments: Value,
+    pub caller: Caller,
+    pub es_requests: Vec<String>,
+    pub duration_ms: u64,
+    pub result_size: usize,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A tool call that has started and will be recorded once finished.
+pub struct AuditEntry {
+    timestamp: SystemTime,
+    start: Instant,
+    tool: String,
+    arguments: Value,
+    caller: Caller,
+}
+
+pub struct AuditLog {
+    sender: mpsc::UnboundedSender<AuditRecord>,
+    redact: Vec<String>,
+}
+
+impl AuditLog {
+    /// Create the audit log and spawn the task that writes to its sink. `es_client` is used by the
+    /// Elasticsearch sink, with the server's credentials.
+    pub fn new(config: &AuditConfig, es_client: &Elasticsearch) -> anyhow::Result<Self> {
+        let mut sink = match &config.sink {
+            AuditSink::Stderr => Sink::Stderr,
+            AuditSink::File(file) => Sink::File(RotatingFile::open(file)?),
+            AuditSink::Elasticsearch(es) => Sink::Elasticsearch(es_client.clone(), es.index.clone()),
+        };
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<AuditRecord>();
+        tokio::spawn(async move {
+            while let Some(record) = receiver.recv().await {
+                if let Err(err) = sink.write(&record).await {
+                    tracing::error!("Failed to write audit record: {err}");
+                }
+            }
+        });
+
+        Ok(AuditLog {
+            sender,
+            redact: config.redact.iter().map(|s| s.to_lowercase()).collect(),
+        })
+    }
+
+    pub fn start(&self, request: &CallToolRequestParam, caller: Caller) -> AuditEntry {
+        let mut arguments = request.arguments.clone().map(Value::Object).unwrap_or(Value::Null);
+        self.redact_value(&mut arguments);
+
+        AuditEntry {
+            timestamp: SystemTime::now(),
+            start: Instant::now(),
+            tool: request.name.to_string(),
+            arguments,
+            caller,
+        }
+    }
+
+    pub fn finish(&self, entry: AuditEntry, es_requests: Vec<String>, result: &Result<CallToolResult, rmcp::Error>) {
+        let (success, error, result_size) = match result {
+            Ok(result) if result.is_error == Some(true) => {
+                let text = result.content.iter().find_map(|c| c.as_text()).map(|t| t.text.clone());
+                (false, text, 0)
+            }
+            Ok(result) => (true, None, serde_json::to_vec(result).map(|v| v.len()).unwrap_or(0)),
+            Err(err) => (false, Some(err.message.to_string()), 0),
+        };
+
+        let record = AuditRecord {
+            timestamp: DateTime::<Utc>::from(entry.timestamp).to_rfc3339_opts(SecondsFormat::Millis, true),
+            tool: entry.tool,
+            arguments: entry.arguments,
+            caller: entry.caller,
+            es_requests,
+            duration_ms: entry.start.elapsed().as_millis() as u64,
+            result_size,
+            success,
+            error,
+        };
+
+        // Can only fail if the writer task has stopped
+        let _ = self.sender.send(record);
+    }
+
+    fn redact_value(&self, value: &mut Value) {
+        match value {
+            Value::Object(obj) => {
+                for (key, value) in obj.iter_mut() {
+                    let key = key.to_lowercase();
+                    if SECRET_NAMES.contains(&key.as_str()) || self.redact.contains(&key) {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact_value(value);
+                    }
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|v| self.redact_value(v)),
+            _ => {}
+        }
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Sinks
+
+enum Sink {
+    Stderr,
+    File(RotatingFile),
+    Elasticsearch(Elasticsearch, String),
+}
+
+impl Sink {
+    async fn write(&mut self, record: &AuditRecord) -> anyhow::Result<()> {
+        match self {
+            Sink::Stderr => {
+                let mut line = serde_json::to_vec(record)?;
+                line.push(b'\n');
+                std::io::stderr().lock().write_all(&line)?;
+            }
+            Sink::File(file) => {
+                let mut line = serde_json::to_vec(record)?;
+                line.push(b'\n');
+                file.write_line(&line)?;
+            }
+            Sink::Elasticsearch(client, index) => {
+                client
+                    .index(IndexParts::Index(index))
+                    .body(record)
+                    .send()
+                    .await?
+                    .error_for_status_code()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A JSON Lines file that is rotated when it exceeds a maximum size. Rotated files are renamed
+/// with a numeric suffix (`audit.log.1` is the most recent) and deleted when there are more
+/// than `max_files` of them or when they're older than `max_age`.
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    max_age: Option<Duration>,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(config: &FileAuditSink) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&config.path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: config.path.clone(),
+            max_size: config.max_size_mb * 1024 * 1024,
+            max_files: config.max_files,
+            max_age: config.max_age_days.map(|days| Duration::from_secs(days * 24 * 3600)),
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> std::io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            // No rotated file to keep: start over
+            self.file = File::create(&self.path)?;
+            self.size = 0;
+            return Ok(());
+        }
+
+        let _ = std::fs::remove_file(self.rotated_path(self.max_files));
+        for n in (1..self.max_files).rev() {
+            let _ = std::fs::rename(self.rotated_path(n), self.rotated_path(n + 1));
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))?;
+
+        if let Some(max_age) = self.max_age {
+            for n in 1..=self.max_files {
+                let path = self.rotated_path(n);
+                let expired = std::fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > max_age));
+                if expired {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redact_whole_names() {
+        let audit = AuditLog {
+            sender: mpsc::unbounded_channel().0,
+            redact: vec!["customer_id".to_string()],
+        };
+        let mut arguments = json!({
+            "index": "orders",
+            "Password": "changeme",
+            "query_body": {
+                "query": {"term": {"customer_id": "c-42"}},
+                "aggs": {"tokens": {"terms": {"field": "token_count", "size": 10}}},
+                "max_tokens": 100
+            },
+            "headers": [{"authorization": "ApiKey xyz"}]
+        });
+        audit.redact_value(&mut arguments);
+
+        assert_eq!(
+            json!({
+                "index": "orders",
+                "Password": REDACTED,
+                "query_body": {
+                    "query": {"term": {"customer_id": REDACTED}},
+                    "aggs": {"tokens": {"terms": {"field": "token_count", "size": 10}}},
+                    "max_tokens": 100
+                },
+                "headers": [{"authorization": REDACTED}]
+            }),
+            arguments
+        );
+    }
+}
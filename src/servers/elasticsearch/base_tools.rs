@@ -15,35 +15,225 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use crate::servers::elasticsearch::{EsClientProvider, read_json};
+use crate::servers::elasticsearch::audit::{self, AuditLog};
+use crate::servers::elasticsearch::caller::Caller;
+use crate::servers::elasticsearch::discovery_tools;
+use crate::servers::elasticsearch::errors;
+use crate::servers::elasticsearch::pagination::{self, next_cursor_content};
+use crate::servers::elasticsearch::query_tools;
+use crate::servers::elasticsearch::rate_limit::RateLimiter;
+use crate::servers::elasticsearch::{
+    ElasticsearchMcpConfig, EsClientProvider, OutputConfig, internal_error, read_json,
+};
 use elasticsearch::cat::{CatIndicesParts, CatShardsParts};
 use elasticsearch::indices::IndicesGetMappingParts;
+use elasticsearch::params::ExpandWildcards;
 use elasticsearch::{Elasticsearch, SearchParts};
 use indexmap::IndexMap;
-use rmcp::handler::server::tool::{Parameters, ToolRouter};
+use rmcp::handler::server::tool::{Parameters, ToolCallContext, ToolRouter};
 use rmcp::model::{
-    CallToolResult, Content, Implementation, JsonObject, ProtocolVersion, ServerCapabilities, ServerInfo,
+    CallToolRequestParam, CallToolResult, Content, Implementation, JsonObject, ListToolsResult, PaginatedRequestParam,
+    ProtocolVersion, ServerCapabilities, ServerInfo,
 };
 use rmcp::service::RequestContext;
 use rmcp::{RoleServer, ServerHandler};
-use rmcp_macros::{tool, tool_handler, tool_router};
+use rmcp_macros::{tool, tool_router};
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::*;
 use serde_json::{Map, Value, json};
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+const DEFAULT_INDICES_LIMIT: usize = 100;
 
 #[derive(Clone)]
 pub struct EsBaseTools {
-    es_client: EsClientProvider,
+    pub(super) es_client: EsClientProvider,
     tool_router: ToolRouter<EsBaseTools>,
+    audit: Option<Arc<AuditLog>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    trusted_proxies: Arc<[IpAddr]>,
+    output: Arc<OutputConfig>,
 }
 
 impl EsBaseTools {
-    pub fn new(es_client: Elasticsearch) -> Self {
-        Self {
+    pub fn new(es_client: Elasticsearch, config: &ElasticsearchMcpConfig) -> anyhow::Result<Self> {
+        let mut tool_router = Self::tool_router()
+            + Self::async_tool_router()
+            + Self::discovery_tool_router()
+            + Self::query_tool_router()
+            + Self::cluster_tool_router()
+            + Self::semantic_tool_router()
+            + Self::document_tool_router()
+            + Self::analytics_tool_router()
+            + Self::log_tool_router()
+            + Self::apm_tool_router();
+        if config.admin_tools {
+            tool_router += Self::admin_tool_router();
+        }
+
+        let audit = match &config.audit {
+            Some(audit) => Some(Arc::new(AuditLog::new(audit, &es_client)?)),
+            None => None,
+        };
+
+        let rate_limiter = config.limits.clone().map(RateLimiter::new).transpose()?.map(Arc::new);
+
+        Ok(Self {
             es_client: EsClientProvider::new(es_client),
-            tool_router: Self::tool_router(),
+            tool_router,
+            audit,
+            rate_limiter,
+            trusted_proxies: config.trusted_proxies.clone().into(),
+            output: Arc::new(config.output.clone()),
+        })
+    }
+
+    /// Call a tool, if the caller is within its rate limits
+    async fn call_tool_limited(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+        caller: &Caller,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let _permit = match &self.rate_limiter {
+            Some(limiter) => Some(limiter.acquire(&caller.key(limiter.key_by()), &request.name)?),
+            None => None,
+        };
+
+        let tcc = ToolCallContext::new(self, request, context.clone());
+        let mut result = self.tool_router.call(tcc).await;
+
+        // Help the LLM find the index it was looking for
+        if let Err(err) = &result
+            && let Some(index) = errors::missing_index(err)
+        {
+            let suggestions = discovery_tools::suggest_indices(&self.es_client.get(context), index).await;
+            result = result.map_err(|err| errors::with_suggestions(err, suggestions));
+        }
+
+        result.or_else(errors::into_tool_result)
+    }
+
+    /// Add the hits' sources to the results, within the output budget of a tool.
+    /// Returns the number of hits that were added.
+    pub(super) fn push_hits(&self, tool: &str, hits: &Hits, results: &mut Vec<Content>) -> Result<usize, rmcp::Error> {
+        self.push_hits_as(tool, hits, HitFormat::Source, results)
+    }
+
+    /// Add the hits to the results in a given format, within the output budget of a tool.
+    /// Returns the number of hits that were added.
+    pub(super) fn push_hits_as(
+        &self,
+        tool: &str,
+        hits: &Hits,
+        format: HitFormat,
+        results: &mut Vec<Content>,
+    ) -> Result<usize, rmcp::Error> {
+        if hits.hits.is_empty() {
+            return Ok(0);
+        }
+
+        let values = hits
+            .hits
+            .iter()
+            .map(|hit| hit.output(format))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(internal_error)?;
+
+        let total = hits.total.as_ref().map(|t| t.value);
+        let (values, note) = self.output.budget(tool).fit_hits(values, total);
+
+        results.push(Content::json(&values)?);
+        if let Some(note) = note {
+            results.push(Content::text(note));
         }
+        Ok(values.len())
+    }
+
+    /// Run a search, continuing from `cursor` if provided. Returns the response and the contents
+    /// for its hits, followed by the cursor of the next page.
+    ///
+    /// Hits of follow-up pages have the `format` of the first page, that is kept in the cursor.
+    async fn paginated_search(
+        &self,
+        es_client: &Elasticsearch,
+        tool: &str,
+        index: &str,
+        query_body: Map<String, Value>,
+        cursor: Option<String>,
+        format: HitFormat,
+    ) -> Result<(SearchResult, Vec<Content>), rmcp::Error> {
+        let (response, pager) = pagination::search(es_client, index, query_body, cursor.as_deref(), format).await?;
+
+        let mut hits = Vec::new();
+        let shown = self.push_hits_as(tool, &response.hits, pager.format(), &mut hits)?;
+        if let Some(cursor) = pager.next_cursor(es_client, &response, shown).await? {
+            hits.push(next_cursor_content(&cursor)?);
+        }
+
+        Ok((response, hits))
+    }
+
+    /// Add aggregation results to the results, within the output budget of a tool.
+    pub(super) fn push_aggregations(
+        &self,
+        tool: &str,
+        mut aggregations: IndexMap<String, Value>,
+        results: &mut Vec<Content>,
+    ) -> Result<(), rmcp::Error> {
+        if aggregations.is_empty() {
+            return Ok(());
+        }
+
+        let note = self.output.budget(tool).fit_aggregations(&mut aggregations);
+
+        results.push(Content::json(&aggregations)?);
+        if let Some(note) = note {
+            results.push(Content::text(note));
+        }
+        Ok(())
+    }
+
+    /// Add table rows to the results as an array of objects, within the output budget of a tool.
+    pub(super) fn push_rows(
+        &self,
+        tool: &str,
+        rows: Vec<Value>,
+        results: &mut Vec<Content>,
+    ) -> Result<(), rmcp::Error> {
+        let (rows, note) = self.output.budget(tool).fit_hits(rows, None);
+
+        results.push(Content::json(rows)?);
+        if let Some(note) = note {
+            results.push(Content::text(note));
+        }
+        Ok(())
+    }
+
+    /// Add ES|QL results to the results as an array of objects, within the output budget of a tool.
+    pub(super) fn push_esql_results(
+        &self,
+        tool: &str,
+        response: EsqlQueryResponse,
+        results: &mut Vec<Content>,
+    ) -> Result<(), rmcp::Error> {
+        if response.is_partial == Some(true) {
+            results.push(Content::text(
+                "Results are partial: the query is still running, or some shards or remote clusters failed.",
+            ));
+        }
+
+        let clusters = response.clusters.clone();
+
+        self.push_rows(tool, response.into_objects(), results)?;
+
+        if let Some(clusters) = clusters {
+            results.push(Content::text("Cross-cluster search metadata:"));
+            results.push(Content::json(clusters)?);
+        }
+        Ok(())
     }
 }
 
@@ -51,6 +241,63 @@ impl EsBaseTools {
 struct ListIndicesParams {
     /// Index pattern of Elasticsearch indices to list
     pub index_pattern: String,
+
+    /// Group the backing indices of data streams under their data stream (optional, default false)
+    pub group_by_data_stream: Option<bool>,
+
+    /// Additional columns to return (optional, default: none)
+    pub columns: Option<Vec<IndexColumn>>,
+
+    /// Sort order, sizes, doc counts and dates being sorted in descending order (optional, default: name)
+    pub sort_by: Option<IndexSort>,
+
+    /// Include hidden and system indices, whose name usually starts with a dot (optional, default false)
+    pub include_hidden: Option<bool>,
+
+    /// Maximum number of indices to return (optional, default: 100)
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum IndexColumn {
+    Health,
+    Primaries,
+    Replicas,
+    StoreSize,
+    CreationDate,
+}
+
+impl IndexColumn {
+    fn cat_header(self) -> &'static str {
+        match self {
+            IndexColumn::Health => "health",
+            IndexColumn::Primaries => "pri",
+            IndexColumn::Replicas => "rep",
+            IndexColumn::StoreSize => "store.size",
+            IndexColumn::CreationDate => "creation.date.string",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum IndexSort {
+    Name,
+    Size,
+    DocCount,
+    CreationDate,
+}
+
+impl IndexSort {
+    fn cat_sort(self) -> &'static str {
+        match self {
+            IndexSort::Name => "index",
+            IndexSort::Size => "store.size:desc",
+            IndexSort::DocCount => "docs.count:desc",
+            IndexSort::CreationDate => "creation.date:desc",
+        }
+    }
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -69,12 +316,80 @@ struct SearchParams {
 
     /// Complete Elasticsearch query DSL object that can include query, size, from, sort, etc.
     query_body: Map<String, Value>, // note: just Value doesn't work, as Claude would send a string
+
+    /// Validate the query before running it, to get readable errors if it's invalid (optional, default false)
+    validate: Option<bool>,
+
+    /// Include the `_id`, `_index`, `_score`, `highlight`, `fields`, `inner_hits` and `sort` of hits (optional,
+    /// default: true when searching several indices). If the query has a `highlight` and no `_source` or `fields`,
+    /// hits have their highlights instead of their source, unless this is `false`.
+    include_metadata: Option<bool>,
+
+    /// Cursor returned as `next_cursor` by a previous call with the same arguments, to get the next page of results
+    cursor: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 struct EsqlQueryParams {
     /// Complete Elasticsearch ES|QL query
     query: String,
+
+    #[serde(flatten)]
+    options: EsqlOptions,
+
+    /// Cursor returned as `next_cursor` by a previous call with the same query, to get the results of a running query
+    cursor: Option<String>,
+}
+
+/// Options of ES|QL queries
+#[derive(Debug, Default, serde::Deserialize, schemars::JsonSchema)]
+pub(super) struct EsqlOptions {
+    /// Query parameters: values of `?` placeholders (e.g. `[10, "error"]`),
+    /// or values of named `?name` placeholders (e.g. `{"limit": 10, "level": "error"}`)
+    params: Option<Value>,
+
+    /// Query DSL filter applied to documents before running the query (e.g. a range on `@timestamp`)
+    filter: Option<Map<String, Value>>,
+
+    /// Locale used to format dates and numbers (e.g. "en-US")
+    locale: Option<String>,
+
+    /// Time zone of dates (e.g. "Europe/Paris", default: "UTC")
+    time_zone: Option<String>,
+
+    /// Include cross-cluster search metadata in the response
+    include_ccs_metadata: Option<bool>,
+}
+
+impl EsqlOptions {
+    /// Create the request for an ES|QL query with these options.
+    pub(super) fn into_request(self, query: String) -> Result<EsqlQueryRequest, rmcp::Error> {
+        let params = match self.params {
+            None | Some(Value::Null) => None,
+            Some(Value::Array(params)) => Some(params),
+            // Named parameters are sent as single-entry objects
+            Some(Value::Object(params)) => {
+                Some(params.into_iter().map(|(name, value)| json!({ name: value })).collect())
+            }
+            Some(_) => {
+                return Err(rmcp::Error::invalid_params(
+                    "ES|QL params must be an array of values or an object of named values",
+                    None,
+                ));
+            }
+        };
+
+        Ok(EsqlQueryRequest {
+            query,
+            columnar: true,
+            params,
+            filter: self.filter,
+            locale: self.locale,
+            time_zone: self.time_zone,
+            include_ccs_metadata: self.include_ccs_metadata,
+            ..Default::default()
+        })
+    }
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -96,6 +411,9 @@ struct ObservabilityQueryParams {
     
     /// Maximum number of results to return
     size: Option<u32>,
+    
+    /// Cursor returned as `next_cursor` by a previous call with the same arguments, to get the next page of results
+    cursor: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -138,6 +456,9 @@ struct TraceAnalysisParams {
     
     /// Maximum number of results
     size: Option<u32>,
+    
+    /// Cursor returned as `next_cursor` by a previous call with the same arguments, to get the next page of results
+    cursor: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -159,6 +480,9 @@ struct LogAnalysisParams {
     
     /// Maximum number of results
     size: Option<u32>,
+    
+    /// Cursor returned as `next_cursor` by a previous call with the same arguments, to get the next page of results
+    cursor: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
@@ -172,29 +496,79 @@ impl EsBaseTools {
     //---------------------------------------------------------------------------------------------
     /// Tool: list indices
     #[tool(
-        description = "List all available Elasticsearch indices",
+        description = "List Elasticsearch indices, with optional health, shard counts, size and creation date. \
+            Can be sorted by size, doc count or creation date.",
         annotations(title = "List ES indices", read_only_hint = true)
     )]
     async fn list_indices(
         &self,
         req_ctx: RequestContext<RoleServer>,
-        Parameters(ListIndicesParams { index_pattern }): Parameters<ListIndicesParams>,
+        Parameters(ListIndicesParams {
+            index_pattern,
+            group_by_data_stream,
+            columns,
+            sort_by,
+            include_hidden,
+            limit,
+        }): Parameters<ListIndicesParams>,
     ) -> Result<CallToolResult, rmcp::Error> {
         let es_client = self.es_client.get(req_ctx);
+
+        let mut headers = vec!["index", "status", "docs.count"];
+        headers.extend(columns.unwrap_or_default().into_iter().map(IndexColumn::cat_header));
+        let expand_wildcards = if include_hidden.unwrap_or(false) {
+            &[ExpandWildcards::All][..]
+        } else {
+            &[ExpandWildcards::Open, ExpandWildcards::Closed][..]
+        };
+
         let response = es_client
             .cat()
             .indices(CatIndicesParts::Index(&[&index_pattern]))
-            .h(&["index", "status", "docs.count"])
+            .h(&headers)
+            .s(&[sort_by.unwrap_or(IndexSort::Name).cat_sort()])
+            .expand_wildcards(expand_wildcards)
             .format("json")
             .send()
             .await;
 
-        let response: Vec<CatIndexResponse> = read_json(response).await?;
+        let mut response: Vec<CatIndexResponse> = read_json(response).await?;
+        let limit = limit.unwrap_or(DEFAULT_INDICES_LIMIT);
 
-        Ok(CallToolResult::success(vec![
-            Content::text(format!("Found {} indices:", response.len())),
-            Content::json(response)?,
-        ]))
+        if !group_by_data_stream.unwrap_or(false) {
+            let total = response.len();
+            response.truncate(limit);
+            let header = if response.len() < total {
+                format!("Found {total} indices, showing {}:", response.len())
+            } else {
+                format!("Found {total} indices:")
+            };
+            let mut results = vec![Content::text(header), Content::json(&response)?];
+            if response.len() < total {
+                results.push(Content::text(truncated_indices_note(total - response.len())));
+            }
+            return Ok(CallToolResult::success(results));
+        }
+
+        let data_streams = discovery_tools::data_streams(&es_client, "*").await?;
+        let (mut data_streams, mut indices) = group_indices(data_streams, response);
+        let total = data_streams.len() + indices.len();
+        data_streams.truncate(limit);
+        indices.truncate(limit - data_streams.len());
+
+        let mut results = vec![
+            Content::text(format!(
+                "Found {total} data streams and other indices, showing {} data streams and {} other indices:",
+                data_streams.len(),
+                indices.len()
+            )),
+            Content::json(json!({ "data_streams": data_streams, "indices": indices }))?,
+        ];
+        let shown = data_streams.len() + indices.len();
+        if shown < total {
+            results.push(Content::text(truncated_indices_note(total - shown)));
+        }
+        Ok(CallToolResult::success(results))
     }
 
     //---------------------------------------------------------------------------------------------
@@ -242,12 +616,37 @@ impl EsBaseTools {
             index,
             fields,
             query_body,
+            validate,
+            include_metadata,
+            cursor,
         }): Parameters<SearchParams>,
     ) -> Result<CallToolResult, rmcp::Error> {
         let es_client = self.es_client.get(req_ctx);
 
         let mut query_body = query_body;
 
+        if validate.unwrap_or(false)
+            && cursor.is_none()
+            && let Some(Value::Object(query)) = query_body.get("query")
+        {
+            let validation = query_tools::validate(&es_client, &index, query.clone()).await?;
+            if !validation.valid {
+                return Ok(validation.into_error());
+            }
+        }
+
+        // Highlights are more focused than the full source of hits
+        let highlights_only =
+            query_body.contains_key("highlight") && !query_body.contains_key("_source") && fields.is_none();
+        let format = match (include_metadata, highlights_only) {
+            (Some(false), _) => HitFormat::Source,
+            (_, true) => HitFormat::Highlights,
+            (Some(true), _) => HitFormat::Metadata,
+            // Hits of several indices need their `_index`
+            (None, false) if index.contains(['*', ',']) => HitFormat::Metadata,
+            (None, false) => HitFormat::Source,
+        };
+
         if let Some(fields) = fields {
             // Augment _source if it exists
             if let Some(Value::Array(values)) = query_body.get_mut("_source") {
@@ -259,13 +658,9 @@ impl EsBaseTools {
             }
         }
 
-        let response = es_client
-            .search(SearchParts::Index(&[&index]))
-            .body(query_body)
-            .send()
-            .await;
-
-        let response: SearchResult = read_json(response).await?;
+        let (response, hits) = self
+            .paginated_search(&es_client, "search", &index, query_body, cursor, format)
+            .await?;
 
         let mut results: Vec<Content> = Vec::new();
 
@@ -274,6 +669,7 @@ impl EsBaseTools {
             let total = response
                 .hits
                 .total
+                .as_ref()
                 .map(|t| t.value.to_string())
                 .unwrap_or("unknown".to_string());
 
@@ -288,14 +684,11 @@ impl EsBaseTools {
         // for hit in &response.hits.hits {
         //     results.push(Content::json(&hit.source)?);
         // }
-        if !response.hits.hits.is_empty() {
-            let sources = response.hits.hits.iter().map(|hit| &hit.source).collect::<Vec<_>>();
-            results.push(Content::json(&sources)?);
-        }
+        results.extend(hits);
 
         if !response.aggregations.is_empty() {
             results.push(Content::text("Aggregations results:"));
-            results.push(Content::json(&response.aggregations)?);
+            self.push_aggregations("search", response.aggregations, &mut results)?;
         }
 
         Ok(CallToolResult::success(results))
@@ -310,29 +703,24 @@ impl EsBaseTools {
     async fn esql(
         &self,
         req_ctx: RequestContext<RoleServer>,
-        Parameters(EsqlQueryParams { query }): Parameters<EsqlQueryParams>,
+        Parameters(EsqlQueryParams { query, options, cursor }): Parameters<EsqlQueryParams>,
     ) -> Result<CallToolResult, rmcp::Error> {
         let es_client = self.es_client.get(req_ctx);
 
-        let request = EsqlQueryRequest { query };
+        let request = options.into_request(query)?;
 
-        let response = es_client.esql().query().body(request).send().await;
-        let response: EsqlQueryResponse = read_json(response).await?;
+        let (response, next_cursor) = pagination::esql(&es_client, request, cursor.as_deref()).await?;
 
-        // Transform response into an array of objects
-        let mut objects: Vec<Value> = Vec::new();
-        for row in response.values.into_iter() {
-            let mut obj = Map::new();
-            for (i, value) in row.into_iter().enumerate() {
-                obj.insert(response.columns[i].name.clone(), value);
-            }
-            objects.push(Value::Object(obj));
+        if let Some(cursor) = next_cursor {
+            return Ok(CallToolResult::success(vec![
+                Content::text("The query is still running, call again with the cursor to get its results."),
+                next_cursor_content(&cursor)?,
+            ]));
         }
 
-        Ok(CallToolResult::success(vec![
-            Content::text("Results"),
-            Content::json(objects)?,
-        ]))
+        let mut results = vec![Content::text("Results")];
+        self.push_esql_results("esql", response, &mut results)?;
+        Ok(CallToolResult::success(results))
     }
 
     //---------------------------------------------------------------------------------------------
@@ -386,6 +774,7 @@ impl EsBaseTools {
             time_range,
             filters,
             size,
+            cursor,
         }): Parameters<ObservabilityQueryParams>,
     ) -> Result<CallToolResult, rmcp::Error> {
         let es_client = self.es_client.get(req_ctx);
@@ -427,18 +816,22 @@ impl EsBaseTools {
             }
         }]));
 
-        let response = es_client
-            .search(SearchParts::Index(&[&index_pattern]))
-            .body(query_body)
-            .send()
-            .await;
-
-        let response: SearchResult = read_json(response).await?;
+        let (response, hits) = self
+            .paginated_search(
+                &es_client,
+                "query_observability_data",
+                &index_pattern,
+                query_body,
+                cursor,
+                HitFormat::Source,
+            )
+            .await?;
 
         let mut results: Vec<Content> = Vec::new();
         let total = response
             .hits
             .total
+            .as_ref()
             .map(|t| t.value.to_string())
             .unwrap_or("unknown".to_string());
 
@@ -448,10 +841,7 @@ impl EsBaseTools {
             time_range
         )));
 
-        if !response.hits.hits.is_empty() {
-            let sources = response.hits.hits.iter().map(|hit| &hit.source).collect::<Vec<_>>();
-            results.push(Content::json(&sources)?);
-        }
+        results.extend(hits);
 
         Ok(CallToolResult::success(results))
     }
@@ -543,9 +933,7 @@ impl EsBaseTools {
             time_range
         )));
 
-        if !response.aggregations.is_empty() {
-            results.push(Content::json(&response.aggregations)?);
-        }
+        self.push_aggregations("aggregate_metrics", response.aggregations, &mut results)?;
 
         Ok(CallToolResult::success(results))
     }
@@ -566,6 +954,7 @@ impl EsBaseTools {
             service_name,
             operation_name,
             size,
+            cursor,
         }): Parameters<TraceAnalysisParams>,
     ) -> Result<CallToolResult, rmcp::Error> {
         let es_client = self.es_client.get(req_ctx);
@@ -625,18 +1014,22 @@ impl EsBaseTools {
             }
         }]));
 
-        let response = es_client
-            .search(SearchParts::Index(&[&index_pattern]))
-            .body(query_body)
-            .send()
-            .await;
-
-        let response: SearchResult = read_json(response).await?;
+        let (response, hits) = self
+            .paginated_search(
+                &es_client,
+                "analyze_traces",
+                &index_pattern,
+                query_body,
+                cursor,
+                HitFormat::Source,
+            )
+            .await?;
 
         let mut results: Vec<Content> = Vec::new();
         let total = response
             .hits
             .total
+            .as_ref()
             .map(|t| t.value.to_string())
             .unwrap_or("unknown".to_string());
 
@@ -646,10 +1039,7 @@ impl EsBaseTools {
             time_range
         )));
 
-        if !response.hits.hits.is_empty() {
-            let sources = response.hits.hits.iter().map(|hit| &hit.source).collect::<Vec<_>>();
-            results.push(Content::json(&sources)?);
-        }
+        results.extend(hits);
 
         Ok(CallToolResult::success(results))
     }
@@ -670,6 +1060,7 @@ impl EsBaseTools {
             service_name,
             search_query,
             size,
+            cursor,
         }): Parameters<LogAnalysisParams>,
     ) -> Result<CallToolResult, rmcp::Error> {
         let es_client = self.es_client.get(req_ctx);
@@ -730,18 +1121,22 @@ impl EsBaseTools {
             }
         }]));
 
-        let response = es_client
-            .search(SearchParts::Index(&[&index_pattern]))
-            .body(query_body)
-            .send()
-            .await;
-
-        let response: SearchResult = read_json(response).await?;
+        let (response, hits) = self
+            .paginated_search(
+                &es_client,
+                "analyze_logs",
+                &index_pattern,
+                query_body,
+                cursor,
+                HitFormat::Source,
+            )
+            .await?;
 
         let mut results: Vec<Content> = Vec::new();
         let total = response
             .hits
             .total
+            .as_ref()
             .map(|t| t.value.to_string())
             .unwrap_or("unknown".to_string());
 
@@ -751,10 +1146,7 @@ impl EsBaseTools {
             time_range
         )));
 
-        if !response.hits.hits.is_empty() {
-            let sources = response.hits.hits.iter().map(|hit| &hit.source).collect::<Vec<_>>();
-            results.push(Content::json(&sources)?);
-        }
+        results.extend(hits);
 
         Ok(CallToolResult::success(results))
     }
@@ -805,7 +1197,6 @@ impl EsBaseTools {
     }
 }
 
-#[tool_handler]
 impl ServerHandler for EsBaseTools {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
@@ -815,6 +1206,32 @@ impl ServerHandler for EsBaseTools {
             instructions: Some("Provides access to Elasticsearch".to_string()),
         }
     }
+
+    // Same as the `#[tool_handler]` macro, with rate limiting and audit logging.
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let caller = Caller::from_context(&context, &self.trusted_proxies);
+        let Some(audit) = &self.audit else {
+            return self.call_tool_limited(request, context, &caller).await;
+        };
+
+        let entry = audit.start(&request, caller.clone());
+        let (result, es_requests) = audit::track_es_requests(self.call_tool_limited(request, context, &caller)).await;
+        audit.finish(entry, es_requests, &result);
+
+        result
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, rmcp::Error> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
 }
 
 //-------------------------------------------------------------------------------------------------
@@ -824,6 +1241,10 @@ impl ServerHandler for EsBaseTools {
 
 #[derive(Serialize, Deserialize)]
 pub struct SearchResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pit_id: Option<String>,
+    #[serde(rename = "_shards", skip_serializing_if = "Option::is_none")]
+    pub shards: Option<ShardStats>,
     pub hits: Hits,
     #[serde(default)]
     pub aggregations: IndexMap<String, Value>,
@@ -835,6 +1256,14 @@ pub struct Hits {
     pub hits: Vec<Hit>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct ShardStats {
+    pub total: u32,
+    pub successful: u32,
+    #[serde(default)]
+    pub failed: u32,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TotalHits {
     pub value: u64,
@@ -842,8 +1271,52 @@ pub struct TotalHits {
 
 #[derive(Serialize, Deserialize)]
 pub struct Hit {
-    #[serde(rename = "_source")]
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "_index", skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    #[serde(rename = "_score", skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    #[serde(rename = "_source", default, skip_serializing_if = "Value::is_null")]
     pub source: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_hits: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<Vec<Value>>,
+}
+
+/// How hits are returned to the client
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HitFormat {
+    /// Only their source
+    Source,
+    /// Their source and metadata: `_id`, `_index`, `_score`, `highlight`, `fields`, `inner_hits` and `sort`
+    Metadata,
+    /// Their metadata, and their highlights instead of their source when they have some
+    Highlights,
+}
+
+impl Hit {
+    fn output(&self, format: HitFormat) -> Result<Value, serde_json::Error> {
+        match format {
+            HitFormat::Source => Ok(self.source.clone()),
+            HitFormat::Metadata => serde_json::to_value(self),
+            HitFormat::Highlights => {
+                let mut value = serde_json::to_value(self)?;
+                if self.highlight.is_some()
+                    && let Value::Object(hit) = &mut value
+                {
+                    hit.remove("_source");
+                }
+                Ok(value)
+            }
+        }
+    }
 }
 
 //----- Cat responses
@@ -852,10 +1325,79 @@ pub struct Hit {
 pub struct CatIndexResponse {
     pub index: String,
     pub status: String,
-    #[serde(rename = "docs.count", deserialize_with = "deserialize_number_from_string")]
+    /// `null` for closed indices
+    #[serde(
+        rename = "docs.count",
+        default,
+        deserialize_with = "deserialize_option_number_from_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub doc_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<String>,
+    #[serde(
+        rename = "pri",
+        default,
+        deserialize_with = "deserialize_option_number_from_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub primaries: Option<u32>,
+    #[serde(
+        rename = "rep",
+        default,
+        deserialize_with = "deserialize_option_number_from_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub replicas: Option<u32>,
+    #[serde(rename = "store.size", default, skip_serializing_if = "Option::is_none")]
+    pub store_size: Option<String>,
+    #[serde(rename = "creation.date.string", default, skip_serializing_if = "Option::is_none")]
+    pub creation_date: Option<String>,
+}
+
+fn truncated_indices_note(count: usize) -> String {
+    format!("{count} more indices not shown. Use a more specific `index_pattern` or a higher `limit` to see them.")
+}
+
+/// Backing indices of a data stream, in a grouped index list
+#[derive(Serialize)]
+pub struct DataStreamIndices {
+    pub data_stream: String,
+    pub backing_indices: usize,
     pub doc_count: u64,
 }
 
+/// Group the backing indices of data streams under their data stream. Returns the data streams
+/// and the indices that don't belong to a data stream.
+fn group_indices(
+    data_streams: Vec<discovery_tools::DataStream>,
+    indices: Vec<CatIndexResponse>,
+) -> (Vec<DataStreamIndices>, Vec<CatIndexResponse>) {
+    let stream_of = data_streams
+        .iter()
+        .flat_map(|ds| ds.indices.iter().map(|i| (i.index_name.as_str(), ds.name.as_str())))
+        .collect::<HashMap<_, _>>();
+
+    let mut groups: IndexMap<&str, DataStreamIndices> = IndexMap::new();
+    let mut others = Vec::new();
+    for index in indices {
+        match stream_of.get(index.index.as_str()) {
+            Some(stream) => {
+                let group = groups.entry(stream).or_insert_with(|| DataStreamIndices {
+                    data_stream: stream.to_string(),
+                    backing_indices: 0,
+                    doc_count: 0,
+                });
+                group.backing_indices += 1;
+                group.doc_count += index.doc_count.unwrap_or(0);
+            }
+            None => others.push(index),
+        }
+    }
+
+    (groups.into_values().collect(), others)
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CatShardsResponse {
     pub index: String,
@@ -863,10 +1405,12 @@ pub struct CatShardsResponse {
     pub shard: usize,
     pub prirep: String,
     pub state: String,
-    #[serde(deserialize_with = "deserialize_option_number_from_string")]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub docs: Option<u64>,
     pub store: Option<String>,
     pub node: Option<String>,
+    #[serde(rename = "unassigned.reason", skip_serializing_if = "Option::is_none")]
+    pub unassigned_reason: Option<String>,
 }
 
 //----- Index mappings
@@ -895,9 +1439,25 @@ pub struct MappingProperty {
 
 //----- ES|QL
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct EsqlQueryRequest {
     pub query: String,
+    /// Values are returned per column, which is more compact than per row
+    pub columnar: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_ccs_metadata: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait_for_completion_timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -909,9 +1469,32 @@ pub struct Column {
 
 #[derive(Serialize, Deserialize)]
 pub struct EsqlQueryResponse {
+    /// Id of async queries, if they're still running or their results are stored
+    pub id: Option<String>,
+    #[serde(default)]
+    pub is_running: bool,
     pub is_partial: Option<bool>,
+    #[serde(default)]
     pub columns: Vec<Column>,
+    /// Values per column, as requests are columnar
+    #[serde(default)]
     pub values: Vec<Vec<Value>>,
+    #[serde(rename = "_clusters", skip_serializing_if = "Option::is_none")]
+    pub clusters: Option<Value>,
+}
+
+impl EsqlQueryResponse {
+    /// Transform columnar values into an array of objects, one per row
+    pub fn into_objects(self) -> Vec<Value> {
+        let row_count = self.values.first().map_or(0, Vec::len);
+        let mut rows = vec![Map::new(); row_count];
+        for (column, values) in self.columns.into_iter().zip(self.values) {
+            for (row, value) in rows.iter_mut().zip(values) {
+                row.insert(column.name.clone(), value);
+            }
+        }
+        rows.into_iter().map(Value::Object).collect()
+    }
 }
 
 //-------------------------------------------------------------------------------------------------
@@ -919,7 +1502,7 @@ pub struct EsqlQueryResponse {
 
 /// Parse time range string into start and end times
 /// Supports formats like "now-1h", "now-1d", "2024-01-01T00:00:00Z,2024-01-01T23:59:59Z"
-fn parse_time_range(time_range: &str) -> Result<(String, String), rmcp::Error> {
+pub(super) fn parse_time_range(time_range: &str) -> Result<(String, String), rmcp::Error> {
     if time_range.contains(',') {
         // Absolute time range format: "start,end"
         let parts: Vec<&str> = time_range.split(',').collect();
@@ -989,3 +1572,144 @@ fn build_metric_aggregation(aggregation_type: &str, field: &str) -> Value {
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn esql_params() {
+        let options = EsqlOptions {
+            params: Some(json!({"limit": 10, "level": "error"})),
+            ..Default::default()
+        };
+        let request = options.into_request("FROM logs | LIMIT ?limit".to_string()).unwrap();
+        // Maps are sorted by key
+        assert_eq!(
+            Some(vec![json!({"level": "error"}), json!({"limit": 10})]),
+            request.params
+        );
+        assert!(request.columnar);
+
+        let options = EsqlOptions {
+            params: Some(json!([10, "error"])),
+            ..Default::default()
+        };
+        let request = options.into_request("FROM logs".to_string()).unwrap();
+        assert_eq!(Some(vec![json!(10), json!("error")]), request.params);
+
+        let options = EsqlOptions {
+            params: Some(json!("10")),
+            ..Default::default()
+        };
+        assert!(options.into_request("FROM logs".to_string()).is_err());
+    }
+
+    #[test]
+    fn esql_columnar_values() {
+        let response: EsqlQueryResponse = serde_json::from_value(json!({
+            "columns": [{"name": "host", "type": "keyword"}, {"name": "count", "type": "long"}],
+            "values": [["a", "b"], [1, 2]]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            vec![json!({"host": "a", "count": 1}), json!({"host": "b", "count": 2})],
+            response.into_objects()
+        );
+    }
+
+    #[test]
+    fn indices_grouped_by_data_stream() {
+        let data_streams: Vec<discovery_tools::DataStream> = serde_json::from_value(json!([{
+            "name": "logs-nginx-default",
+            "indices": [
+                {"index_name": ".ds-logs-nginx-default-2025.01.01-000001"},
+                {"index_name": ".ds-logs-nginx-default-2025.01.02-000002"}
+            ]
+        }]))
+        .unwrap();
+        let indices: Vec<CatIndexResponse> = serde_json::from_value(json!([
+            {"index": ".ds-logs-nginx-default-2025.01.01-000001", "status": "open", "docs.count": "10"},
+            {"index": "products", "status": "open", "docs.count": "3"},
+            {"index": ".ds-logs-nginx-default-2025.01.02-000002", "status": "open", "docs.count": "5"}
+        ]))
+        .unwrap();
+
+        let (data_streams, indices) = group_indices(data_streams, indices);
+        assert_eq!(
+            json!([{"data_stream": "logs-nginx-default", "backing_indices": 2, "doc_count": 15}]),
+            serde_json::to_value(data_streams).unwrap()
+        );
+        assert_eq!(
+            vec!["products"],
+            indices.iter().map(|i| i.index.as_str()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn cat_index_columns() {
+        let index: CatIndexResponse = serde_json::from_value(json!({
+            "index": "products",
+            "status": "open",
+            "docs.count": "3",
+            "health": "green",
+            "pri": "1",
+            "rep": "0",
+            "store.size": "12.5kb"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            json!({
+                "index": "products",
+                "status": "open",
+                "docs.count": 3,
+                "health": "green",
+                "pri": 1,
+                "rep": 0,
+                "store.size": "12.5kb"
+            }),
+            serde_json::to_value(index).unwrap()
+        );
+
+        // Closed indices have no document count
+        let index: CatIndexResponse = serde_json::from_value(json!({
+            "index": "archive",
+            "status": "close",
+            "docs.count": null,
+            "health": "green"
+        }))
+        .unwrap();
+        assert_eq!(None, index.doc_count);
+    }
+
+    #[test]
+    fn hit_formats() {
+        let hit: Hit = serde_json::from_value(json!({
+            "_index": "products",
+            "_id": "1",
+            "_score": 1.5,
+            "_source": {"name": "red shoes", "description": "Comfortable red shoes"},
+            "highlight": {"description": ["Comfortable <em>red</em> shoes"]}
+        }))
+        .unwrap();
+
+        assert_eq!(
+            json!({"name": "red shoes", "description": "Comfortable red shoes"}),
+            hit.output(HitFormat::Source).unwrap()
+        );
+        assert_eq!(
+            json!({
+                "_id": "1",
+                "_index": "products",
+                "_score": 1.5,
+                "highlight": {"description": ["Comfortable <em>red</em> shoes"]}
+            }),
+            hit.output(HitFormat::Highlights).unwrap()
+        );
+
+        let metadata = hit.output(HitFormat::Metadata).unwrap();
+        assert_eq!(Some(&json!("red shoes")), metadata.pointer("/_source/name"));
+    }
+}
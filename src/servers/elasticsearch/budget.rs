@@ -0,0 +1,244 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Output budget of tool results, so that they fit in the LLM context window.
+//!
+//! Results that exceed the budget are reduced by truncating long strings, shortening arrays
+//! and dropping hits. A note is then added so that the LLM can narrow its query.
+
+use crate::servers::elasticsearch::{OutputConfig, OutputLimits};
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+
+const DEFAULT_MAX_TOKENS: usize = 20_000;
+const DEFAULT_MAX_STRING_LENGTH: usize = 2_000;
+const DEFAULT_MAX_ARRAY_LENGTH: usize = 100;
+
+/// Rough approximation of the number of bytes per token for JSON data
+const BYTES_PER_TOKEN: usize = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutputBudget {
+    pub max_bytes: usize,
+    pub max_string_length: usize,
+    pub max_array_length: usize,
+}
+
+impl OutputConfig {
+    /// The output budget of a tool: its specific limits, then the global limits, then the defaults.
+    pub fn budget(&self, tool: &str) -> OutputBudget {
+        let tool_limits = self.tools.get(tool);
+        let get = |f: fn(&OutputLimits) -> Option<usize>| tool_limits.and_then(f).or_else(|| f(&self.default));
+
+        let max_tokens = get(|l| l.max_tokens).unwrap_or(DEFAULT_MAX_TOKENS);
+        let max_bytes = match get(|l| l.max_bytes) {
+            Some(max_bytes) => max_bytes.min(max_tokens.saturating_mul(BYTES_PER_TOKEN)),
+            None => max_tokens.saturating_mul(BYTES_PER_TOKEN),
+        };
+
+        OutputBudget {
+            max_bytes,
+            max_string_length: get(|l| l.max_string_length).unwrap_or(DEFAULT_MAX_STRING_LENGTH),
+            max_array_length: get(|l| l.max_array_length).unwrap_or(DEFAULT_MAX_ARRAY_LENGTH),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    fields_truncated: usize,
+    arrays_shortened: usize,
+}
+
+impl OutputBudget {
+    /// Fit a list of hits (documents or rows) in the budget. `total` is the total number of hits
+    /// matching the query, if known. Returns the hits to send and a note if they were reduced.
+    pub fn fit_hits(&self, hits: Vec<Value>, total: Option<u64>) -> (Vec<Value>, Option<String>) {
+        let count = hits.len();
+        let mut stats = Stats::default();
+        let mut size = 2; // array brackets
+        let mut result = Vec::new();
+
+        for mut hit in hits {
+            let mut hit_stats = Stats::default();
+            self.truncate(&mut hit, &mut hit_stats);
+            let hit_size = json_size(&hit) + 1;
+
+            // Always keep the first hit, even if it's too large
+            if !result.is_empty() && size + hit_size > self.max_bytes {
+                break;
+            }
+            size += hit_size;
+            stats.fields_truncated += hit_stats.fields_truncated;
+            stats.arrays_shortened += hit_stats.arrays_shortened;
+            result.push(hit);
+        }
+
+        let note = if result.len() < count || stats.fields_truncated > 0 || stats.arrays_shortened > 0 {
+            let total = total.unwrap_or(count as u64).max(count as u64);
+            Some(note(format!("showing {} of {} hits", result.len(), total), &stats))
+        } else {
+            None
+        };
+
+        (result, note)
+    }
+
+    /// Fit aggregation results in the budget by truncating strings and arrays (e.g. buckets).
+    /// Returns a note if they were reduced.
+    pub fn fit_aggregations(&self, aggregations: &mut IndexMap<String, Value>) -> Option<String> {
+        let original = aggregations.clone();
+        let mut budget = *self;
+
+        loop {
+            let mut stats = Stats::default();
+            aggregations.values_mut().for_each(|v| budget.truncate(v, &mut stats));
+
+            if json_size(aggregations) <= self.max_bytes || budget.max_array_length <= 1 {
+                return (stats.fields_truncated > 0 || stats.arrays_shortened > 0)
+                    .then(|| note("aggregation results were reduced".to_string(), &stats));
+            }
+
+            // Still too large: retry with shorter arrays
+            budget.max_array_length /= 2;
+            *aggregations = original.clone();
+        }
+    }
+
+    fn truncate(&self, value: &mut Value, stats: &mut Stats) {
+        match value {
+            Value::String(s) => {
+                let len = s.chars().count();
+                if len > self.max_string_length {
+                    let kept: String = s.chars().take(self.max_string_length).collect();
+                    *s = format!("{kept}… [truncated {} chars]", len - self.max_string_length);
+                    stats.fields_truncated += 1;
+                }
+            }
+            Value::Array(values) => {
+                let more = values.len().saturating_sub(self.max_array_length);
+                values.truncate(self.max_array_length);
+                values.iter_mut().for_each(|v| self.truncate(v, stats));
+                if more > 0 {
+                    values.push(Value::String(format!("… [{more} more items]")));
+                    stats.arrays_shortened += 1;
+                }
+            }
+            Value::Object(obj) => obj.values_mut().for_each(|v| self.truncate(v, stats)),
+            _ => {}
+        }
+    }
+}
+
+fn json_size(value: &impl Serialize) -> usize {
+    serde_json::to_vec(value).map(|v| v.len()).unwrap_or(0)
+}
+
+fn note(mut text: String, stats: &Stats) -> String {
+    if stats.fields_truncated > 0 {
+        text.push_str(&format!(", {} fields truncated", stats.fields_truncated));
+    }
+    if stats.arrays_shortened > 0 {
+        text.push_str(&format!(", {} arrays shortened", stats.arrays_shortened));
+    }
+
+    // Capitalize, as it starts the sentence
+    let mut chars = text.chars();
+    let text = match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => text,
+    };
+
+    format!("{text}. Output is limited to fit the context window: narrow the query or select fewer fields to see more.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const BUDGET: OutputBudget = OutputBudget {
+        max_bytes: 100,
+        max_string_length: 10,
+        max_array_length: 3,
+    };
+
+    #[test]
+    fn fit_hits() {
+        // Each hit is 22 bytes
+        let hits = (0..9).map(|i| json!({"id": i, "msg": "short"})).collect::<Vec<_>>();
+        let (hits, note) = BUDGET.fit_hits(hits, Some(350));
+        assert_eq!(4, hits.len());
+        assert!(note.unwrap().starts_with("Showing 4 of 350 hits."));
+
+        let hits = vec![json!({"id": 1, "msg": "a longer message"})];
+        let (hits, note) = BUDGET.fit_hits(hits, None);
+        assert_eq!(json!("a longer m… [truncated 6 chars]"), hits[0]["msg"]);
+        assert!(note.unwrap().starts_with("Showing 1 of 1 hits, 1 fields truncated."));
+    }
+
+    #[test]
+    fn fit_hits_within_budget() {
+        let hits = vec![json!({"id": 1, "tags": ["a", "b"]})];
+        let (hits, note) = BUDGET.fit_hits(hits, Some(1));
+        assert_eq!(1, hits.len());
+        assert!(note.is_none());
+    }
+
+    #[test]
+    fn fit_aggregations() {
+        let mut aggs = IndexMap::from([("by_host".to_string(), json!({"buckets": [1, 2, 3, 4, 5]}))]);
+        let note = BUDGET.fit_aggregations(&mut aggs);
+        assert_eq!(json!({"buckets": [1, 2, 3, "… [2 more items]"]}), aggs["by_host"]);
+        assert!(
+            note.unwrap()
+                .starts_with("Aggregation results were reduced, 1 arrays shortened.")
+        );
+
+        // Too large even with truncated arrays: arrays are shortened further
+        let mut aggs = ["a", "b", "c"]
+            .map(|name| (name.to_string(), json!((1..=20).collect::<Vec<_>>())))
+            .into_iter()
+            .collect::<IndexMap<_, _>>();
+        assert!(BUDGET.fit_aggregations(&mut aggs).is_some());
+        assert_eq!(json!([1, "… [19 more items]"]), aggs["a"]);
+        assert!(json_size(&aggs) <= BUDGET.max_bytes);
+    }
+
+    #[test]
+    fn budget_config() {
+        let config = OutputConfig {
+            default: OutputLimits {
+                max_tokens: Some(1000),
+                ..Default::default()
+            },
+            tools: [(
+                "esql".to_string(),
+                OutputLimits {
+                    max_bytes: Some(100),
+                    ..Default::default()
+                },
+            )]
+            .into(),
+        };
+
+        assert_eq!(4000, config.budget("search").max_bytes);
+        assert_eq!(100, config.budget("esql").max_bytes);
+        assert_eq!(DEFAULT_MAX_STRING_LENGTH, config.budget("esql").max_string_length);
+    }
+}
@@ -0,0 +1,219 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Identity of the client calling a tool, extracted from the HTTP request.
+
+use crate::servers::elasticsearch::RateLimitKey;
+use axum::extract::ConnectInfo;
+use base64::prelude::*;
+use http::header;
+use http::request::Parts;
+use rmcp::RoleServer;
+use rmcp::service::RequestContext;
+use serde::Serialize;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+
+/// Identity of a caller. All fields are empty for stdio clients.
+///
+/// Secrets are never stored: API keys are identified by their id, basic auth by the user name,
+/// and other credentials by a short fingerprint.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq, Hash)]
+pub struct Caller {
+    /// User name, API key id or credentials fingerprint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    /// Client IP address: the peer address, or the one it forwarded in `X-Forwarded-For` if it's a
+    /// trusted proxy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl Caller {
+    pub fn from_context(context: &RequestContext<RoleServer>, trusted_proxies: &[IpAddr]) -> Self {
+        let Some(parts) = context.extensions.get::<Parts>() else {
+            return Caller::default();
+        };
+
+        let header = |name: header::HeaderName| parts.headers.get(name).and_then(|h| h.to_str().ok());
+
+        let user = header(header::AUTHORIZATION).map(identify);
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let client_ip = peer.map(|peer| {
+            let forwarded_for = parts
+                .headers
+                .get_all(header::HeaderName::from_static("x-forwarded-for"))
+                .iter()
+                .filter_map(|h| h.to_str().ok());
+            client_address(peer, forwarded_for, trusted_proxies).to_string()
+        });
+
+        Caller {
+            user,
+            client_ip,
+            user_agent: header(header::USER_AGENT).map(str::to_string),
+        }
+    }
+
+    /// A key identifying this caller, for rate limiting. Falls back to the IP address if the user
+    /// is unknown, and to `anonymous` if both are.
+    pub fn key(&self, key_by: RateLimitKey) -> String {
+        match (key_by, &self.user, &self.client_ip) {
+            (RateLimitKey::User, Some(user), _) => user.clone(),
+            (RateLimitKey::UserIp, Some(user), Some(ip)) => format!("{user}@{ip}"),
+            (RateLimitKey::UserIp, Some(user), None) => user.clone(),
+            (_, _, Some(ip)) => ip.clone(),
+            _ => "anonymous".to_string(),
+        }
+    }
+}
+
+/// Address of the client: the peer address, unless it's a trusted proxy. In that case, it's the
+/// last address of `X-Forwarded-For` that isn't a trusted proxy, as the addresses before it can
+/// be set to anything by the client.
+fn client_address<'a>(
+    peer: IpAddr,
+    forwarded_for: impl Iterator<Item = &'a str>,
+    trusted_proxies: &[IpAddr],
+) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+
+    let forwarded_for = forwarded_for
+        .flat_map(|h| h.split(','))
+        .map(|ip| ip.trim().parse::<IpAddr>())
+        .collect::<Vec<_>>();
+
+    let mut client = peer;
+    for ip in forwarded_for.into_iter().rev() {
+        // An invalid address can't be trusted, nor anything before it
+        let Ok(ip) = ip else { break };
+        client = ip;
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+    }
+    client
+}
+
+/// Identify the user of an `Authorization` header without exposing its secret part.
+fn identify(auth: &str) -> String {
+    // Same as EsClientProvider: the MCP inspector prepends "Bearer" to the value provided
+    let mut auth = auth;
+    if auth.starts_with("Bearer ApiKey ") || auth.starts_with("Bearer Basic ") {
+        auth = auth.trim_start_matches("Bearer ");
+    }
+
+    let (scheme, credentials) = auth.split_once(' ').unwrap_or(("", auth));
+
+    let decoded = BASE64_STANDARD
+        .decode(credentials.trim())
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|s| s.split_once(':').map(|(id, _secret)| id.to_string()));
+
+    match (scheme.to_ascii_lowercase().as_str(), decoded) {
+        ("apikey", Some(id)) => format!("api_key:{id}"),
+        ("basic", Some(user)) => format!("user:{user}"),
+        _ => {
+            let mut hasher = DefaultHasher::new();
+            auth.hash(&mut hasher);
+            format!("credentials:{:08x}", hasher.finish() as u32)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identify_without_secrets() {
+        assert_eq!("api_key:key-id", identify("ApiKey a2V5LWlkOnNlY3JldA=="));
+        assert_eq!("api_key:key-id", identify("Bearer ApiKey a2V5LWlkOnNlY3JldA=="));
+        assert_eq!("user:elastic", identify("Basic ZWxhc3RpYzpjaGFuZ2VtZQ=="));
+
+        let token = identify("Bearer some-opaque-token");
+        assert!(token.starts_with("credentials:"));
+        assert!(!token.contains("opaque"));
+    }
+
+    #[test]
+    fn rate_limit_keys() {
+        let caller = Caller {
+            user: Some("user:elastic".to_string()),
+            client_ip: Some("1.2.3.4".to_string()),
+            user_agent: None,
+        };
+        assert_eq!("1.2.3.4", caller.key(RateLimitKey::Ip));
+        assert_eq!("user:elastic", caller.key(RateLimitKey::User));
+        assert_eq!("user:elastic@1.2.3.4", caller.key(RateLimitKey::UserIp));
+
+        let no_user = Caller {
+            user: None,
+            ..caller.clone()
+        };
+        assert_eq!("1.2.3.4", no_user.key(RateLimitKey::User));
+        assert_eq!("1.2.3.4", no_user.key(RateLimitKey::UserIp));
+
+        let no_ip = Caller {
+            client_ip: None,
+            ..caller
+        };
+        assert_eq!("anonymous", no_ip.key(RateLimitKey::Ip));
+        assert_eq!("user:elastic", no_ip.key(RateLimitKey::UserIp));
+    }
+
+    #[test]
+    fn forwarded_for_trusted_proxies_only() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+
+        // Not a proxy: the header is ignored
+        let forwarded = ["1.2.3.4"];
+        assert_eq!(
+            ip("192.168.1.1"),
+            client_address(ip("192.168.1.1"), forwarded.into_iter(), &proxies)
+        );
+
+        // Proxies are skipped from the end, and addresses set by the client are ignored
+        let forwarded = ["6.6.6.6, 1.2.3.4", "10.0.0.2"];
+        assert_eq!(
+            ip("1.2.3.4"),
+            client_address(ip("10.0.0.1"), forwarded.into_iter(), &proxies)
+        );
+
+        // A proxy without the header
+        assert_eq!(ip("10.0.0.1"), client_address(ip("10.0.0.1"), [].into_iter(), &proxies));
+
+        // Invalid addresses aren't trusted
+        let forwarded = ["1.2.3.4, garbage"];
+        assert_eq!(
+            ip("10.0.0.1"),
+            client_address(ip("10.0.0.1"), forwarded.into_iter(), &proxies)
+        );
+    }
+}
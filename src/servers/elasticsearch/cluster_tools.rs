@@ -0,0 +1,1027 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cluster diagnostic tools, that help find out why a cluster isn't healthy or is slow.
+
+use crate::servers::elasticsearch::base_tools::{CatShardsResponse, EsBaseTools, SearchResult, parse_time_range};
+use crate::servers::elasticsearch::{OPAQUE_ID, OPAQUE_ID_HEADER, read_json, read_text};
+use elasticsearch::cat::{CatNodesParts, CatShardsParts};
+use elasticsearch::cluster::{
+    ClusterAllocationExplainParts, ClusterGetSettingsParts, ClusterHealthParts, ClusterPendingTasksParts,
+};
+use elasticsearch::indices::IndicesGetSettingsParts;
+use elasticsearch::nodes::NodesHotThreadsParts;
+use elasticsearch::tasks::TasksListParts;
+use elasticsearch::{Elasticsearch, SearchParts};
+use indexmap::IndexMap;
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde::{Deserialize, Serialize};
+use serde_aux::prelude::*;
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+
+const DEFAULT_TASK_ACTIONS: &str = "*search,*esql";
+const DEFAULT_TASKS_LIMIT: usize = 20;
+const DEFAULT_SLOWLOG_INDEX: &str = "logs-elasticsearch.slowlog-*";
+const DEFAULT_SLOWLOG_SIZE: u32 = 10;
+
+/// Maximum length of task descriptions
+const MAX_TASK_DESCRIPTION_LENGTH: usize = 1000;
+
+/// Maximum number of unassigned shards that are explained
+const MAX_EXPLAINED_SHARDS: usize = 5;
+
+/// Maximum number of pending tasks in the report
+const MAX_PENDING_TASKS: usize = 5;
+
+/// Maximum number of lines of the hot threads summary
+const MAX_HOT_THREADS_LINES: usize = 30;
+
+/// Heap usage percentage above which a node is flagged
+const HIGH_HEAP_PERCENT: f64 = 85.0;
+
+/// CPU usage percentage above which a node is flagged
+const HIGH_CPU_PERCENT: f64 = 90.0;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct DiagnoseClusterParams {
+    /// Include the hot threads of each node, to find out what's using CPU (optional, default true)
+    hot_threads: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ListTasksParams {
+    /// Comma-separated action patterns of the tasks to list (optional, default: "*search,*esql", i.e. searches
+    /// and ES|QL queries)
+    actions: Option<String>,
+
+    /// Maximum number of tasks to return, the longest running first (optional, default: 20)
+    limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct SlowlogSummaryParams {
+    /// Index pattern of the indices whose slowlog settings are returned (optional, default: all indices)
+    index: Option<String>,
+
+    /// Index pattern of the indexed slowlogs, as collected by the Elasticsearch integration
+    /// (optional, default: "logs-elasticsearch.slowlog-*")
+    slowlog_index: Option<String>,
+
+    /// Time range of the slowlogs (e.g., "now-1h", "now-1d", "2024-01-01T00:00:00Z,2024-01-01T23:59:59Z",
+    /// default: "now-24h")
+    time_range: Option<String>,
+
+    /// Number of slowest queries to return (optional, default: 10)
+    size: Option<u32>,
+}
+
+#[tool_router(router = cluster_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: diagnose cluster
+    #[tool(
+        description = "Diagnose the cluster health: nodes resource usage, unassigned shards and why they can't be \
+            allocated, disk watermarks, pending tasks and hot threads. Flags the likely root causes of problems.",
+        annotations(title = "Diagnose ES cluster", read_only_hint = true)
+    )]
+    async fn diagnose_cluster(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(DiagnoseClusterParams { hot_threads }): Parameters<DiagnoseClusterParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let response = es_client.cluster().health(ClusterHealthParts::None).send().await;
+        let health: ClusterHealth = read_json(response).await?;
+
+        let response = es_client
+            .cat()
+            .nodes(CatNodesParts::None)
+            .h(&[
+                "name",
+                "node.role",
+                "master",
+                "heap.percent",
+                "ram.percent",
+                "cpu",
+                "load_1m",
+                "disk.used_percent",
+                "disk.avail",
+            ])
+            .format("json")
+            .send()
+            .await;
+        let nodes: Vec<CatNodeResponse> = read_json(response).await?;
+
+        // Some APIs may not be available, e.g. on serverless: report what we can
+        let watermarks = optional("disk watermarks", disk_watermarks(&es_client)).await;
+        let unassigned = optional("unassigned shards", unassigned_shards(&es_client)).await;
+        let pending_tasks = optional("pending tasks", pending_tasks(&es_client)).await;
+        let hot_threads = if hot_threads.unwrap_or(true) {
+            optional("hot threads", hot_threads_summary(&es_client)).await
+        } else {
+            None
+        };
+
+        let report = Report {
+            health,
+            nodes,
+            disk_watermarks: watermarks,
+            unassigned_shards: unassigned,
+            pending_tasks,
+        };
+
+        let findings = report.findings();
+        let mut results = vec![Content::text(format!(
+            "Cluster status is {}. Findings:\n- {}",
+            report.health.status,
+            findings.join("\n- ")
+        ))];
+        results.push(Content::json(&report)?);
+        if let Some(hot_threads) = hot_threads {
+            results.push(Content::text(format!("Hot threads:\n{hot_threads}")));
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: list tasks
+    #[tool(
+        description = "List the running searches and ES|QL queries, or other tasks, the longest running first, \
+            with their description. Tasks started by this server are flagged with `started_by_mcp`.",
+        annotations(title = "List ES tasks", read_only_hint = true)
+    )]
+    async fn list_tasks(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(ListTasksParams { actions, limit }): Parameters<ListTasksParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let actions = actions.unwrap_or_else(|| DEFAULT_TASK_ACTIONS.to_string());
+        let response = es_client
+            .tasks()
+            .list(TasksListParts::None)
+            .actions(&actions.split(',').map(str::trim).collect::<Vec<_>>())
+            .detailed(true)
+            .send()
+            .await;
+        let response: TasksResponse = read_json(response).await?;
+
+        let mut tasks = response.into_summaries();
+        let total = tasks.len();
+        tasks.truncate(limit.unwrap_or(DEFAULT_TASKS_LIMIT));
+
+        Ok(CallToolResult::success(vec![
+            Content::text(format!(
+                "Found {total} tasks matching '{actions}', showing {}:",
+                tasks.len()
+            )),
+            Content::json(tasks)?,
+        ]))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: slowlog summary
+    #[tool(
+        description = "Summarize slow queries: the search and indexing slowlog thresholds of indices, and the \
+            slowest queries found in indexed slowlogs, with the indices that have the most slow queries.",
+        annotations(title = "Summarize ES slowlogs", read_only_hint = true)
+    )]
+    async fn slowlog_summary(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(SlowlogSummaryParams {
+            index,
+            slowlog_index,
+            time_range,
+            size,
+        }): Parameters<SlowlogSummaryParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let index = index.unwrap_or_else(|| "*".to_string());
+        let response = es_client
+            .indices()
+            .get_settings(IndicesGetSettingsParts::IndexName(&[&index], SLOWLOG_SETTINGS))
+            .flat_settings(true)
+            .send()
+            .await;
+        let settings: IndexMap<String, IndexSettings> = read_json(response).await?;
+        let thresholds = slowlog_thresholds(settings);
+
+        let mut results = Vec::new();
+        if thresholds.is_empty() {
+            results.push(Content::text(format!(
+                "No slowlog thresholds are set on indices matching '{index}': slowlogs are disabled."
+            )));
+        } else {
+            results.push(Content::text(format!(
+                "Slowlog thresholds of indices matching '{index}':"
+            )));
+            results.push(Content::json(&thresholds)?);
+        }
+
+        let slowlog_index = slowlog_index.unwrap_or_else(|| DEFAULT_SLOWLOG_INDEX.to_string());
+        let time_range = time_range.unwrap_or_else(|| "now-24h".to_string());
+        let (start_time, end_time) = parse_time_range(&time_range)?;
+        let response = es_client
+            .search(SearchParts::Index(&[&slowlog_index]))
+            .ignore_unavailable(true)
+            .body(json!({
+                "size": size.unwrap_or(DEFAULT_SLOWLOG_SIZE),
+                "query": {"bool": {"filter": [
+                    {"range": {"@timestamp": {"gte": start_time, "lte": end_time}}},
+                    {"term": {"event.dataset": "elasticsearch.slowlog"}}
+                ]}},
+                "sort": [{"event.duration": {"order": "desc", "unmapped_type": "long"}}],
+                "_source": [
+                    "@timestamp",
+                    "event.duration",
+                    "elasticsearch.index.name",
+                    "elasticsearch.slowlog.*",
+                    "log.level"
+                ],
+                "aggs": {"indices": {
+                    "terms": {"field": "elasticsearch.index.name", "size": 10},
+                    "aggs": {"max_duration": {"max": {"field": "event.duration"}}}
+                }}
+            }))
+            .send()
+            .await;
+        let response: SearchResult = read_json(response).await?;
+
+        if response.hits.hits.is_empty() {
+            results.push(Content::text(format!(
+                "No indexed slowlogs found in '{slowlog_index}' for time range '{time_range}'. Slowlogs are \
+                indexed when collected by the Elasticsearch integration of Elastic Agent or Filebeat."
+            )));
+        } else {
+            let total = response.hits.total.as_ref().map_or(0, |t| t.value);
+            results.push(Content::text(format!(
+                "Found {total} slowlog entries in '{slowlog_index}' for time range '{time_range}', the slowest first:"
+            )));
+            self.push_hits("slowlog_summary", &response.hits, &mut results)?;
+            if let Some(indices) = response.aggregations.get("indices") {
+                results.push(Content::text("Indices with the most slow queries:"));
+                results.push(Content::json(indices)?);
+            }
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+}
+
+/// Run a diagnostic that isn't essential, logging its failure.
+async fn optional<T>(name: &str, diagnostic: impl Future<Output = Result<T, rmcp::Error>>) -> Option<T> {
+    match diagnostic.await {
+        Ok(result) => Some(result),
+        Err(err) => {
+            tracing::warn!("Failed to get {name}: {}", err.message);
+            None
+        }
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Diagnostics
+
+#[derive(Serialize, Deserialize)]
+struct ClusterHealth {
+    status: String,
+    number_of_nodes: u32,
+    number_of_data_nodes: u32,
+    active_shards_percent_as_number: f64,
+    unassigned_shards: u32,
+    #[serde(default)]
+    delayed_unassigned_shards: u32,
+    initializing_shards: u32,
+    relocating_shards: u32,
+    number_of_pending_tasks: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CatNodeResponse {
+    name: String,
+    #[serde(rename = "node.role")]
+    roles: String,
+    master: String,
+    #[serde(
+        rename = "heap.percent",
+        default,
+        deserialize_with = "deserialize_option_number_from_string"
+    )]
+    heap_percent: Option<f64>,
+    #[serde(
+        rename = "ram.percent",
+        default,
+        deserialize_with = "deserialize_option_number_from_string"
+    )]
+    ram_percent: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    cpu: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    load_1m: Option<f64>,
+    #[serde(
+        rename = "disk.used_percent",
+        default,
+        deserialize_with = "deserialize_option_number_from_string"
+    )]
+    disk_used_percent: Option<f64>,
+    #[serde(rename = "disk.avail")]
+    disk_available: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+struct DiskWatermarks {
+    low: Option<String>,
+    high: Option<String>,
+    flood_stage: Option<String>,
+}
+
+async fn disk_watermarks(es_client: &Elasticsearch) -> Result<DiskWatermarks, rmcp::Error> {
+    let response = es_client
+        .cluster()
+        .get_settings(ClusterGetSettingsParts::None)
+        .include_defaults(true)
+        .flat_settings(true)
+        .send()
+        .await;
+    let settings: IndexMap<String, Map<String, Value>> = read_json(response).await?;
+
+    // Transient settings override persistent settings, that override defaults
+    let setting = |name: &str| {
+        let key = format!("cluster.routing.allocation.disk.watermark.{name}");
+        ["transient", "persistent", "defaults"]
+            .iter()
+            .find_map(|level| settings.get(*level)?.get(&key)?.as_str())
+            .map(str::to_string)
+    };
+
+    Ok(DiskWatermarks {
+        low: setting("low"),
+        high: setting("high"),
+        flood_stage: setting("flood_stage"),
+    })
+}
+
+#[derive(Serialize, Default)]
+struct UnassignedShards {
+    total: usize,
+    primaries: usize,
+    by_reason: IndexMap<String, usize>,
+    explanations: Vec<AllocationExplanation>,
+}
+
+async fn unassigned_shards(es_client: &Elasticsearch) -> Result<UnassignedShards, rmcp::Error> {
+    let response = es_client
+        .cat()
+        .shards(CatShardsParts::None)
+        .h(&["index", "shard", "prirep", "state", "unassigned.reason"])
+        .format("json")
+        .send()
+        .await;
+    let shards: Vec<CatShardsResponse> = read_json(response).await?;
+
+    let mut shards = shards
+        .into_iter()
+        .filter(|s| s.state == "UNASSIGNED")
+        .collect::<Vec<_>>();
+    // Primaries first, as they're the cause of a red status
+    shards.sort_by_key(|s| s.prirep != "p");
+
+    let mut result = UnassignedShards {
+        total: shards.len(),
+        primaries: shards.iter().filter(|s| s.prirep == "p").count(),
+        ..Default::default()
+    };
+    for shard in &shards {
+        let reason = shard.unassigned_reason.clone().unwrap_or_else(|| "unknown".to_string());
+        *result.by_reason.entry(reason).or_default() += 1;
+    }
+
+    for shard in shards.iter().take(MAX_EXPLAINED_SHARDS) {
+        let response = es_client
+            .cluster()
+            .allocation_explain(ClusterAllocationExplainParts::None)
+            .body(json!({ "index": shard.index, "shard": shard.shard, "primary": shard.prirep == "p" }))
+            .send()
+            .await;
+        // A shard may have been assigned since it was listed, which makes the explain API fail
+        let explanation = match read_json::<AllocationExplainResponse>(response).await {
+            Ok(explanation) => explanation.into(),
+            Err(err) => {
+                let message = err.message.to_string();
+                tracing::warn!(
+                    "Failed to explain allocation of shard [{}][{}]: {message}",
+                    shard.index,
+                    shard.shard
+                );
+                AllocationExplanation {
+                    index: shard.index.clone(),
+                    shard: shard.shard,
+                    primary: shard.prirep == "p",
+                    can_allocate: None,
+                    explanation: None,
+                    deciders: IndexMap::new(),
+                    error: Some(message),
+                }
+            }
+        };
+        result.explanations.push(explanation);
+    }
+
+    Ok(result)
+}
+
+#[derive(Deserialize)]
+struct AllocationExplainResponse {
+    index: String,
+    shard: usize,
+    primary: bool,
+    can_allocate: Option<String>,
+    allocate_explanation: Option<String>,
+    #[serde(default)]
+    node_allocation_decisions: Vec<NodeAllocationDecision>,
+}
+
+#[derive(Deserialize)]
+struct NodeAllocationDecision {
+    #[serde(default)]
+    deciders: Vec<Decider>,
+}
+
+#[derive(Deserialize)]
+struct Decider {
+    decider: String,
+    decision: String,
+    explanation: String,
+}
+
+#[derive(Serialize)]
+struct AllocationExplanation {
+    index: String,
+    shard: usize,
+    primary: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    can_allocate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explanation: Option<String>,
+    /// Distinct reasons why nodes refuse the shard, as `decider: explanation`, with their node count
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    deciders: IndexMap<String, usize>,
+    /// Why the allocation couldn't be explained
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<AllocationExplainResponse> for AllocationExplanation {
+    fn from(response: AllocationExplainResponse) -> Self {
+        let mut deciders = IndexMap::new();
+        for decider in response.node_allocation_decisions.iter().flat_map(|d| &d.deciders) {
+            if decider.decision == "NO" {
+                let reason = format!("{}: {}", decider.decider, decider.explanation);
+                *deciders.entry(reason).or_default() += 1;
+            }
+        }
+
+        AllocationExplanation {
+            index: response.index,
+            shard: response.shard,
+            primary: response.primary,
+            can_allocate: response.can_allocate,
+            explanation: response.allocate_explanation,
+            deciders,
+            error: None,
+        }
+    }
+}
+
+#[derive(Serialize, Default)]
+struct PendingTasks {
+    total: usize,
+    oldest: Vec<PendingTask>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingTask {
+    priority: String,
+    source: String,
+    time_in_queue: Option<String>,
+    #[serde(default, skip_serializing)]
+    time_in_queue_millis: u64,
+}
+
+async fn pending_tasks(es_client: &Elasticsearch) -> Result<PendingTasks, rmcp::Error> {
+    #[derive(Deserialize)]
+    struct PendingTasksResponse {
+        tasks: Vec<PendingTask>,
+    }
+
+    let response = es_client
+        .cluster()
+        .pending_tasks(ClusterPendingTasksParts::None)
+        .send()
+        .await;
+    let mut tasks = read_json::<PendingTasksResponse>(response).await?.tasks;
+
+    let total = tasks.len();
+    tasks.sort_by_key(|t| std::cmp::Reverse(t.time_in_queue_millis));
+    tasks.truncate(MAX_PENDING_TASKS);
+    Ok(PendingTasks { total, oldest: tasks })
+}
+
+async fn hot_threads_summary(es_client: &Elasticsearch) -> Result<String, rmcp::Error> {
+    let response = es_client
+        .nodes()
+        .hot_threads(NodesHotThreadsParts::None)
+        .threads(3)
+        .ignore_idle_threads(true)
+        .send()
+        .await;
+    let text = read_text(response).await?;
+    Ok(summarize_hot_threads(&text))
+}
+
+/// Keep the node headers and the CPU usage of each thread, without the stack traces.
+fn summarize_hot_threads(text: &str) -> String {
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with(":::") || line.contains("cpu usage by thread"))
+        .collect::<Vec<_>>();
+
+    let mut summary = lines
+        .iter()
+        .take(MAX_HOT_THREADS_LINES)
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if lines.len() > MAX_HOT_THREADS_LINES {
+        summary.push_str(&format!("\n… [{} more lines]", lines.len() - MAX_HOT_THREADS_LINES));
+    }
+    summary
+}
+
+//-------------------------------------------------------------------------------------------------
+// Tasks
+
+#[derive(Deserialize)]
+struct TasksResponse {
+    #[serde(default)]
+    nodes: IndexMap<String, NodeTasks>,
+}
+
+#[derive(Deserialize)]
+struct NodeTasks {
+    name: String,
+    #[serde(default)]
+    tasks: IndexMap<String, TaskInfo>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct TaskInfo {
+    node: String,
+    id: u64,
+    action: String,
+    description: Option<String>,
+    running_time_in_nanos: u64,
+    #[serde(default)]
+    pub cancellable: bool,
+    #[serde(default)]
+    cancelled: bool,
+    parent_task_id: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub(super) struct TaskSummary {
+    task_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node: Option<String>,
+    action: String,
+    running_time: String,
+    cancellable: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    cancelled: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    started_by_mcp: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_task_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+impl TaskInfo {
+    pub(super) fn summary(self, node_name: Option<String>) -> TaskSummary {
+        let started_by_mcp = self
+            .headers
+            .iter()
+            .any(|(name, value)| name.eq_ignore_ascii_case(OPAQUE_ID_HEADER) && value == OPAQUE_ID);
+
+        // Descriptions of searches contain their request body, that can be large
+        let description = self.description.filter(|d| !d.is_empty()).map(|mut d| {
+            if let Some((pos, _)) = d.char_indices().nth(MAX_TASK_DESCRIPTION_LENGTH) {
+                d.truncate(pos);
+                d.push('…');
+            }
+            d
+        });
+
+        TaskSummary {
+            task_id: format!("{}:{}", self.node, self.id),
+            node: node_name,
+            action: self.action,
+            running_time: format_nanos(self.running_time_in_nanos),
+            cancellable: self.cancellable,
+            cancelled: self.cancelled,
+            started_by_mcp,
+            parent_task_id: self.parent_task_id,
+            description,
+        }
+    }
+}
+
+impl TasksResponse {
+    /// Task summaries, the longest running first.
+    fn into_summaries(self) -> Vec<TaskSummary> {
+        let mut tasks = self
+            .nodes
+            .into_values()
+            .flat_map(|node| {
+                let name = node.name;
+                node.tasks
+                    .into_values()
+                    .map(move |task| (task.running_time_in_nanos, task, name.clone()))
+            })
+            .collect::<Vec<_>>();
+        tasks.sort_by_key(|(running_time, _, _)| std::cmp::Reverse(*running_time));
+        tasks
+            .into_iter()
+            .map(|(_, task, node)| task.summary(Some(node)))
+            .collect()
+    }
+}
+
+/// Format a duration in nanoseconds in a human readable way, e.g. `1m 5s`, `2.5s` or `120ms`.
+fn format_nanos(nanos: u64) -> String {
+    let millis = nanos / 1_000_000;
+    match millis {
+        0..1_000 => format!("{millis}ms"),
+        1_000..60_000 => format!("{:.1}s", millis as f64 / 1000.0),
+        _ => format!("{}m {}s", millis / 60_000, millis % 60_000 / 1000),
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Slowlogs
+
+/// Slowlog threshold settings, as `flat_settings` patterns
+const SLOWLOG_SETTINGS: &[&str] = &["index.search.slowlog.threshold.*", "index.indexing.slowlog.threshold.*"];
+
+#[derive(Deserialize)]
+struct IndexSettings {
+    #[serde(default)]
+    settings: BTreeMap<String, Value>,
+}
+
+/// Indices grouped by their slowlog thresholds
+#[derive(Serialize, Debug, PartialEq)]
+struct SlowlogThresholds {
+    thresholds: BTreeMap<String, String>,
+    indices: Vec<String>,
+}
+
+/// Group indices by their enabled slowlog thresholds, omitting those without any.
+fn slowlog_thresholds(settings: IndexMap<String, IndexSettings>) -> Vec<SlowlogThresholds> {
+    let mut groups: Vec<SlowlogThresholds> = Vec::new();
+    for (index, settings) in settings {
+        let thresholds = settings
+            .settings
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let value = value.as_str()?.to_string();
+                // -1 disables a threshold
+                let name = name.strip_prefix("index.")?.to_string();
+                (value != "-1").then_some((name, value))
+            })
+            .collect::<BTreeMap<_, _>>();
+        if thresholds.is_empty() {
+            continue;
+        }
+
+        match groups.iter_mut().find(|g| g.thresholds == thresholds) {
+            Some(group) => group.indices.push(index),
+            None => groups.push(SlowlogThresholds {
+                thresholds,
+                indices: vec![index],
+            }),
+        }
+    }
+    groups
+}
+
+//-------------------------------------------------------------------------------------------------
+// Report
+
+#[derive(Serialize)]
+struct Report {
+    health: ClusterHealth,
+    nodes: Vec<CatNodeResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disk_watermarks: Option<DiskWatermarks>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unassigned_shards: Option<UnassignedShards>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pending_tasks: Option<PendingTasks>,
+}
+
+impl Report {
+    /// Likely root causes of problems, most important first.
+    fn findings(&self) -> Vec<String> {
+        let mut findings = Vec::new();
+        let health = &self.health;
+
+        if health.status != "green" {
+            let primaries = match &self.unassigned_shards {
+                Some(unassigned) => format!(" ({} primaries)", unassigned.primaries),
+                None => String::new(),
+            };
+            findings.push(format!(
+                "{} unassigned shards{primaries}, {} initializing, {} relocating.",
+                health.unassigned_shards, health.initializing_shards, health.relocating_shards
+            ));
+        }
+
+        if let Some(unassigned) = &self.unassigned_shards {
+            if health.number_of_data_nodes == 1 && unassigned.total > unassigned.primaries {
+                findings.push(
+                    "Replicas can't be allocated on a single data node: set `index.number_of_replicas` to 0 \
+                    or add data nodes."
+                        .to_string(),
+                );
+            }
+            for explanation in &unassigned.explanations {
+                let kind = if explanation.primary { "primary" } else { "replica" };
+                let reason = explanation.deciders.keys().next().or(explanation.explanation.as_ref());
+                if let Some(reason) = reason {
+                    findings.push(format!(
+                        "Shard [{}][{}] ({kind}) can't be allocated: {reason}",
+                        explanation.index, explanation.shard
+                    ));
+                }
+            }
+        }
+
+        let watermarks = self.disk_watermarks.as_ref();
+        let watermark = |name: &str, value: Option<&String>| Some((name.to_string(), percentage(value?)?));
+        let watermarks = [
+            watermark("flood stage", watermarks.and_then(|w| w.flood_stage.as_ref())),
+            watermark("high", watermarks.and_then(|w| w.high.as_ref())),
+            watermark("low", watermarks.and_then(|w| w.low.as_ref())),
+        ];
+        for node in &self.nodes {
+            if let Some(disk) = node.disk_used_percent
+                && let Some((name, limit)) = watermarks.iter().flatten().find(|(_, limit)| disk >= *limit)
+            {
+                let consequence = match name.as_str() {
+                    "flood stage" => "its indices are read-only",
+                    "high" => "shards are being moved away from it",
+                    _ => "no new shards will be allocated to it",
+                };
+                findings.push(format!(
+                    "Node {} disk usage is {disk}%, above the {name} watermark ({limit}%): {consequence}.",
+                    node.name
+                ));
+            }
+            if let Some(heap) = node.heap_percent
+                && heap >= HIGH_HEAP_PERCENT
+            {
+                findings.push(format!(
+                    "Node {} heap usage is {heap}%: risk of long garbage collections and circuit breaker errors.",
+                    node.name
+                ));
+            }
+            if let Some(cpu) = node.cpu
+                && cpu >= HIGH_CPU_PERCENT
+            {
+                findings.push(format!(
+                    "Node {} CPU usage is {cpu}%: check the hot threads for the cause.",
+                    node.name
+                ));
+            }
+        }
+
+        if let Some(pending) = &self.pending_tasks
+            && let Some(oldest) = pending.oldest.first()
+        {
+            findings.push(format!(
+                "{} pending cluster tasks, the oldest ({}) waiting for {}: the master node may be overloaded.",
+                pending.total,
+                oldest.source,
+                oldest.time_in_queue.as_deref().unwrap_or("unknown time")
+            ));
+        }
+
+        if findings.is_empty() {
+            findings.push("No problems found.".to_string());
+        }
+        findings
+    }
+}
+
+/// Parse a watermark percentage like `85%` or a ratio like `0.85`. Absolute values like `500mb` return `None`.
+fn percentage(value: &str) -> Option<f64> {
+    match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse().ok(),
+        None => value.trim().parse::<f64>().ok().map(|ratio| ratio * 100.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watermark_percentages() {
+        assert_eq!(Some(85.0), percentage("85%"));
+        assert_eq!(Some(90.0), percentage("0.9"));
+        assert_eq!(None, percentage("500mb"));
+    }
+
+    #[test]
+    fn report_findings() {
+        let report = Report {
+            health: serde_json::from_value(json!({
+                "status": "yellow",
+                "number_of_nodes": 1,
+                "number_of_data_nodes": 1,
+                "active_shards_percent_as_number": 50.0,
+                "unassigned_shards": 1,
+                "initializing_shards": 0,
+                "relocating_shards": 0,
+                "number_of_pending_tasks": 0
+            }))
+            .unwrap(),
+            nodes: serde_json::from_value(json!([{
+                "name": "node-1",
+                "node.role": "cdfhilmrstw",
+                "master": "*",
+                "heap.percent": "91",
+                "ram.percent": "80",
+                "cpu": "12",
+                "load_1m": "0.5",
+                "disk.used_percent": "87.5",
+                "disk.avail": "10gb"
+            }]))
+            .unwrap(),
+            disk_watermarks: Some(DiskWatermarks {
+                low: Some("85%".to_string()),
+                high: Some("90%".to_string()),
+                flood_stage: Some("95%".to_string()),
+            }),
+            unassigned_shards: Some(UnassignedShards {
+                total: 1,
+                primaries: 0,
+                by_reason: IndexMap::from([("INDEX_CREATED".to_string(), 1)]),
+                explanations: vec![AllocationExplanation {
+                    index: "logs".to_string(),
+                    shard: 0,
+                    primary: false,
+                    can_allocate: Some("no".to_string()),
+                    explanation: None,
+                    deciders: IndexMap::from([(
+                        "same_shard: a copy of this shard is already allocated to this node".to_string(),
+                        1,
+                    )]),
+                    error: None,
+                }],
+            }),
+            pending_tasks: Some(PendingTasks::default()),
+        };
+
+        assert_eq!(
+            vec![
+                "1 unassigned shards (0 primaries), 0 initializing, 0 relocating.",
+                "Replicas can't be allocated on a single data node: set `index.number_of_replicas` to 0 or add data \
+                nodes.",
+                "Shard [logs][0] (replica) can't be allocated: same_shard: a copy of this shard is already allocated \
+                to this node",
+                "Node node-1 disk usage is 87.5%, above the low watermark (85%): no new shards will be allocated to it.",
+                "Node node-1 heap usage is 91%: risk of long garbage collections and circuit breaker errors.",
+            ],
+            report.findings()
+        );
+    }
+
+    #[test]
+    fn hot_threads() {
+        let text = "::: {node-1}{abc}{def}{127.0.0.1}\n   Hot threads at 2025-01-01T00:00:00Z:\n\n   \
+            95.3% [cpu=95.3%, other=0.0%] (476.5ms out of 500ms) cpu usage by thread 'elasticsearch[node-1][search][T#3]'\n     \
+            10/10 snapshots sharing following 2 elements\n       java.base@21/java.lang.Thread.run(Thread.java:1583)\n";
+
+        assert_eq!(
+            "::: {node-1}{abc}{def}{127.0.0.1}\n\
+            95.3% [cpu=95.3%, other=0.0%] (476.5ms out of 500ms) cpu usage by thread 'elasticsearch[node-1][search][T#3]'",
+            summarize_hot_threads(text)
+        );
+    }
+
+    #[test]
+    fn task_summaries() {
+        let response: TasksResponse = serde_json::from_value(json!({
+            "nodes": {
+                "abc": {
+                    "name": "node-1",
+                    "tasks": {
+                        "abc:1": {
+                            "node": "abc",
+                            "id": 1,
+                            "action": "indices:data/read/search",
+                            "description": "indices[logs-*], search_type[QUERY_THEN_FETCH]",
+                            "running_time_in_nanos": 65_500_000_000u64,
+                            "cancellable": true,
+                            "headers": {"X-Opaque-Id": "elastic-mcp"}
+                        },
+                        "abc:2": {
+                            "node": "abc",
+                            "id": 2,
+                            "action": "indices:data/read/esql",
+                            "description": "",
+                            "running_time_in_nanos": 120_000_000,
+                            "cancellable": true,
+                            "cancelled": true,
+                            "parent_task_id": "abc:0"
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            json!([
+                {
+                    "task_id": "abc:1",
+                    "node": "node-1",
+                    "action": "indices:data/read/search",
+                    "running_time": "1m 5s",
+                    "cancellable": true,
+                    "started_by_mcp": true,
+                    "description": "indices[logs-*], search_type[QUERY_THEN_FETCH]"
+                },
+                {
+                    "task_id": "abc:2",
+                    "node": "node-1",
+                    "action": "indices:data/read/esql",
+                    "running_time": "120ms",
+                    "cancellable": true,
+                    "cancelled": true,
+                    "parent_task_id": "abc:0"
+                }
+            ]),
+            serde_json::to_value(response.into_summaries()).unwrap()
+        );
+        assert_eq!("2.5s", format_nanos(2_500_000_000));
+    }
+
+    #[test]
+    fn grouped_slowlog_thresholds() {
+        let settings: IndexMap<String, IndexSettings> = serde_json::from_value(json!({
+            "logs-1": {"settings": {"index.search.slowlog.threshold.query.warn": "10s"}},
+            "logs-2": {"settings": {"index.search.slowlog.threshold.query.warn": "10s"}},
+            "products": {"settings": {"index.search.slowlog.threshold.query.warn": "-1"}}
+        }))
+        .unwrap();
+
+        assert_eq!(
+            vec![SlowlogThresholds {
+                thresholds: BTreeMap::from([("search.slowlog.threshold.query.warn".to_string(), "10s".to_string())]),
+                indices: vec!["logs-1".to_string(), "logs-2".to_string()],
+            }],
+            slowlog_thresholds(settings)
+        );
+    }
+}
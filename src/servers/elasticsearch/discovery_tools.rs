@@ -0,0 +1,882 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Data discovery tools, that help the LLM learn what's in an index before writing queries.
+
+use crate::servers::elasticsearch::base_tools::{EsBaseTools, SearchResult};
+use crate::servers::elasticsearch::{read_json, read_json_opt};
+use crate::utils::edit_distance;
+use elasticsearch::indices::{IndicesGetDataStreamParts, IndicesResolveIndexParts};
+use elasticsearch::{Elasticsearch, FieldCapsParts, SearchParts};
+use indexmap::IndexMap;
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+const DEFAULT_FIELDS_LIMIT: usize = 200;
+const DEFAULT_SAMPLE_SIZE: u32 = 3;
+const DEFAULT_TOP_VALUES: u32 = 10;
+
+/// Field types that have min and max values
+const RANGE_TYPES: &[&str] = &[
+    "long",
+    "integer",
+    "short",
+    "byte",
+    "double",
+    "float",
+    "half_float",
+    "scaled_float",
+    "unsigned_long",
+    "date",
+    "date_nanos",
+];
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct DescribeFieldsParams {
+    /// Name or pattern of the indices, data streams or aliases to describe
+    index: String,
+
+    /// Field name pattern, with `*` wildcards (e.g. "service.*", "*.name", default: all fields)
+    fields: Option<String>,
+
+    /// Maximum number of fields to return (default: 200)
+    limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ProfileIndexParams {
+    /// Name or pattern of the indices, data streams or aliases to profile
+    index: String,
+
+    /// Fields to profile with their top values, cardinality, and min/max for numbers and dates
+    /// (e.g. `["service.name", "http.response.status_code"]`)
+    fields: Option<Vec<String>>,
+
+    /// Number of random sample documents to return (default: 3)
+    sample_size: Option<u32>,
+
+    /// Number of top values to return for each field (default: 10)
+    top_values: Option<u32>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ListDataStreamsParams {
+    /// Name or pattern of the data streams to list, with `*` wildcards (default: all data streams)
+    name: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ResolveIndexParams {
+    /// Name or pattern of the indices, aliases and data streams to resolve, with `*` wildcards (e.g. "logs-*")
+    name: String,
+}
+
+#[tool_router(router = discovery_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: describe fields
+    #[tool(
+        description = "Describe the fields of one or more indices: their type, whether they're searchable and \
+            aggregatable, and type conflicts across indices. Much more compact than mappings.",
+        annotations(title = "Describe ES index fields", read_only_hint = true)
+    )]
+    async fn describe_fields(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(DescribeFieldsParams { index, fields, limit }): Parameters<DescribeFieldsParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let pattern = fields.unwrap_or_else(|| "*".to_string());
+        let response = field_caps(&es_client, &index, &[&pattern]).await?;
+
+        let index_count = response.indices.len();
+        let mut fields = describe(response);
+        let total = fields.len();
+        fields.truncate(limit.unwrap_or(DEFAULT_FIELDS_LIMIT));
+
+        let mut results = vec![Content::text(format!(
+            "Found {total} fields matching '{pattern}' in {index_count} indices, showing {}:",
+            fields.len()
+        ))];
+        results.push(Content::json(&fields)?);
+
+        if fields.len() < total {
+            results.push(Content::text(format!(
+                "{} more fields not shown. Use a more specific `fields` pattern to see them.",
+                total - fields.len()
+            )));
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: profile index
+    #[tool(
+        description = "Profile the content of one or more indices: random sample documents, and for the fields \
+            requested, their top values with counts, cardinality, and min/max for numbers and dates.",
+        annotations(title = "Profile ES index content", read_only_hint = true)
+    )]
+    async fn profile_index(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(ProfileIndexParams {
+            index,
+            fields,
+            sample_size,
+            top_values,
+        }): Parameters<ProfileIndexParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let requested = fields.unwrap_or_default();
+        let fields = if requested.is_empty() {
+            Vec::new()
+        } else {
+            let names = requested.iter().map(String::as_str).collect::<Vec<_>>();
+            describe(field_caps(&es_client, &index, &names).await?)
+        };
+
+        let mut query_body = json!({
+            "size": sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE),
+            "track_total_hits": true,
+            "query": {
+                "function_score": {
+                    "query": { "match_all": {} },
+                    "random_score": {},
+                    "boost_mode": "replace"
+                }
+            },
+        });
+        if !fields.is_empty() {
+            query_body["aggs"] = Value::Object(profile_aggregations(&fields, top_values.unwrap_or(DEFAULT_TOP_VALUES)));
+        }
+
+        let response = es_client
+            .search(SearchParts::Index(&[&index]))
+            .body(query_body)
+            .send()
+            .await;
+        let response: SearchResult = read_json(response).await?;
+
+        let total = response
+            .hits
+            .total
+            .as_ref()
+            .map(|t| t.value.to_string())
+            .unwrap_or("unknown".to_string());
+
+        let mut results = vec![Content::text(format!(
+            "Index '{index}' has {total} documents. Random sample documents:"
+        ))];
+        self.push_hits("profile_index", &response.hits, &mut results)?;
+
+        if !fields.is_empty() {
+            results.push(Content::text("Field profiles:"));
+            let profiles = field_profiles(&fields, &response.aggregations);
+            self.push_aggregations("profile_index", profiles, &mut results)?;
+        }
+
+        let missing = requested
+            .iter()
+            .filter(|name| !fields.iter().any(|f| &f.field == *name))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            results.push(Content::text(format!(
+                "Fields not found: {}. Use `describe_fields` to list the available fields.",
+                missing.join(", ")
+            )));
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: resolve index
+    #[tool(
+        description = "Resolve a name or pattern to the matching indices, aliases and data streams. \
+            Use it to check index names before querying them.",
+        annotations(title = "Resolve ES index names", read_only_hint = true)
+    )]
+    async fn resolve_index(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(ResolveIndexParams { name }): Parameters<ResolveIndexParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let response = resolve(&es_client, &name).await?;
+        let mut results = vec![Content::text(format!(
+            "Found {} indices, {} aliases and {} data streams matching '{name}':",
+            response.indices.len(),
+            response.aliases.len(),
+            response.data_streams.len()
+        ))];
+        results.push(Content::json(ResolvedNames::from(response))?);
+
+        Ok(CallToolResult::success(results))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: list data streams
+    #[tool(
+        description = "List data streams with their index template, lifecycle (ILM policy or data stream \
+            lifecycle), generation, number of backing indices and index mode (e.g. time series).",
+        annotations(title = "List ES data streams", read_only_hint = true)
+    )]
+    async fn list_data_streams(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(ListDataStreamsParams { name }): Parameters<ListDataStreamsParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let name = name.unwrap_or_else(|| "*".to_string());
+        let data_streams = data_streams(&es_client, &name)
+            .await?
+            .into_iter()
+            .map(DataStreamDescription::from)
+            .collect::<Vec<_>>();
+
+        Ok(CallToolResult::success(vec![
+            Content::text(format!("Found {} data streams matching '{name}':", data_streams.len())),
+            Content::json(data_streams)?,
+        ]))
+    }
+}
+
+/// Fields of some indices with their type, sorted by name. The type of fields that have several
+/// types across indices is the types separated by `|`.
+pub(super) async fn field_types(
+    es_client: &Elasticsearch,
+    index: &str,
+    fields: &[&str],
+) -> Result<Vec<(String, String)>, rmcp::Error> {
+    let response = field_caps(es_client, index, fields).await?;
+    Ok(describe(response).into_iter().map(|f| (f.field, f.type_)).collect())
+}
+
+async fn field_caps(es_client: &Elasticsearch, index: &str, fields: &[&str]) -> Result<FieldCapsResponse, rmcp::Error> {
+    let response = es_client
+        .field_caps(FieldCapsParts::Index(&[index]))
+        .fields(fields)
+        .send()
+        .await;
+    read_json(response).await
+}
+
+//-------------------------------------------------------------------------------------------------
+// Field capabilities
+
+#[derive(Deserialize)]
+struct FieldCapsResponse {
+    #[serde(default)]
+    indices: Vec<String>,
+    /// Capabilities by field name and type
+    fields: BTreeMap<String, HashMap<String, FieldCapability>>,
+}
+
+#[derive(Deserialize)]
+struct FieldCapability {
+    #[serde(default)]
+    metadata_field: bool,
+    searchable: bool,
+    aggregatable: bool,
+    /// Indices that have this type, if the field has several types
+    indices: Option<Vec<String>>,
+    non_searchable_indices: Option<Vec<String>>,
+    non_aggregatable_indices: Option<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct FieldDescription {
+    field: String,
+    /// Field type, or types separated by `|` if it differs across indices
+    #[serde(rename = "type")]
+    type_: String,
+    searchable: bool,
+    aggregatable: bool,
+    /// Indices by field type, if the field has several types
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conflicts: Option<BTreeMap<String, Vec<String>>>,
+    /// Indices where the field isn't searchable, if it is searchable in others
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not_searchable_in: Option<Vec<String>>,
+    /// Indices where the field isn't aggregatable, if it is aggregatable in others
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not_aggregatable_in: Option<Vec<String>>,
+}
+
+/// Flatten field capabilities into one description per field, sorted by name. Metadata fields
+/// and objects are skipped, as their sub-fields are listed.
+fn describe(response: FieldCapsResponse) -> Vec<FieldDescription> {
+    let mut result = Vec::new();
+
+    for (field, mut caps) in response.fields {
+        caps.retain(|type_, cap| !cap.metadata_field && type_ != "object");
+        if caps.is_empty() {
+            continue;
+        }
+
+        let mut types = caps.keys().cloned().collect::<Vec<_>>();
+        types.sort();
+
+        let conflicts = (caps.len() > 1).then(|| {
+            caps.iter()
+                .map(|(type_, cap)| (type_.clone(), cap.indices.clone().unwrap_or_default()))
+                .collect::<BTreeMap<_, _>>()
+        });
+
+        let merge = |f: fn(&FieldCapability) -> Option<&Vec<String>>| {
+            let mut indices = caps.values().filter_map(f).flatten().cloned().collect::<Vec<_>>();
+            indices.sort();
+            indices.dedup();
+            (!indices.is_empty()).then_some(indices)
+        };
+
+        result.push(FieldDescription {
+            field,
+            type_: types.join("|"),
+            searchable: caps.values().any(|c| c.searchable),
+            aggregatable: caps.values().any(|c| c.aggregatable),
+            conflicts,
+            not_searchable_in: merge(|c| c.non_searchable_indices.as_ref()),
+            not_aggregatable_in: merge(|c| c.non_aggregatable_indices.as_ref()),
+        });
+    }
+
+    result
+}
+
+//-------------------------------------------------------------------------------------------------
+// Field profiles
+
+/// Aggregations to profile fields. They're named after the field position, as field names may
+/// contain characters that aren't allowed in aggregation names.
+fn profile_aggregations(fields: &[FieldDescription], top_values: u32) -> Map<String, Value> {
+    let mut aggs = Map::new();
+
+    for (i, field) in fields.iter().enumerate().filter(|(_, f)| f.aggregatable) {
+        let name = &field.field;
+        aggs.insert(format!("{i}_cardinality"), json!({ "cardinality": { "field": name } }));
+
+        if RANGE_TYPES.contains(&field.type_.as_str()) {
+            aggs.insert(format!("{i}_min"), json!({ "min": { "field": name } }));
+            aggs.insert(format!("{i}_max"), json!({ "max": { "field": name } }));
+        }
+
+        // Top values of dates aren't meaningful
+        if !field.type_.starts_with("date") {
+            aggs.insert(
+                format!("{i}_terms"),
+                json!({ "terms": { "field": name, "size": top_values } }),
+            );
+        }
+    }
+
+    aggs
+}
+
+/// Compact profile of each field, from the results of [`profile_aggregations`].
+fn field_profiles(fields: &[FieldDescription], aggregations: &IndexMap<String, Value>) -> IndexMap<String, Value> {
+    let mut profiles = IndexMap::new();
+
+    for (i, field) in fields.iter().enumerate() {
+        let mut profile = Map::new();
+        profile.insert("type".to_string(), json!(field.type_));
+
+        if !field.aggregatable {
+            profile.insert(
+                "note".to_string(),
+                json!("not aggregatable, values can only be seen in sample documents"),
+            );
+        }
+
+        let agg = |suffix: &str| aggregations.get(&format!("{i}_{suffix}"));
+
+        if let Some(value) = agg("cardinality").and_then(|a| a.get("value")) {
+            profile.insert("cardinality".to_string(), value.clone());
+        }
+
+        for bound in ["min", "max"] {
+            // Dates have a formatted value
+            if let Some(value) = agg(bound).and_then(|a| a.get("value_as_string").or_else(|| a.get("value"))) {
+                profile.insert(bound.to_string(), value.clone());
+            }
+        }
+
+        if let Some(Value::Array(buckets)) = agg("terms").and_then(|a| a.get("buckets")) {
+            let top_values = buckets
+                .iter()
+                .map(|b| {
+                    json!({
+                        "value": b.get("key_as_string").or_else(|| b.get("key")),
+                        "count": b.get("doc_count"),
+                    })
+                })
+                .collect::<Vec<_>>();
+            profile.insert("top_values".to_string(), Value::Array(top_values));
+        }
+
+        profiles.insert(field.field.clone(), Value::Object(profile));
+    }
+
+    profiles
+}
+
+//-------------------------------------------------------------------------------------------------
+// Data streams
+
+/// Get the data streams matching a name or pattern. Returns an empty list if there are none.
+pub(super) async fn data_streams(es_client: &Elasticsearch, name: &str) -> Result<Vec<DataStream>, rmcp::Error> {
+    let response = es_client
+        .indices()
+        .get_data_stream(IndicesGetDataStreamParts::Name(&[name]))
+        .send()
+        .await;
+    let response: Option<DataStreamsResponse> = read_json_opt(response).await?;
+    Ok(response.map(|r| r.data_streams).unwrap_or_default())
+}
+
+#[derive(Deserialize)]
+struct DataStreamsResponse {
+    data_streams: Vec<DataStream>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct DataStream {
+    pub name: String,
+    #[serde(default)]
+    pub indices: Vec<BackingIndex>,
+    generation: Option<u64>,
+    status: Option<String>,
+    template: Option<String>,
+    ilm_policy: Option<String>,
+    lifecycle: Option<DataStreamLifecycle>,
+    next_generation_managed_by: Option<String>,
+    index_mode: Option<String>,
+    time_series: Option<Value>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct BackingIndex {
+    pub index_name: String,
+}
+
+#[derive(Deserialize)]
+struct DataStreamLifecycle {
+    #[serde(default)]
+    enabled: bool,
+    data_retention: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DataStreamDescription {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    template: Option<String>,
+    /// How new backing indices are managed: ILM policy, data stream lifecycle or unmanaged
+    lifecycle: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation: Option<u64>,
+    backing_indices: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    health: Option<String>,
+    index_mode: String,
+}
+
+impl From<DataStream> for DataStreamDescription {
+    fn from(ds: DataStream) -> Self {
+        let managed_by = ds.next_generation_managed_by.as_deref().unwrap_or_default();
+        let lifecycle = match (&ds.ilm_policy, &ds.lifecycle) {
+            (Some(policy), _) if managed_by != "Data stream lifecycle" => format!("ILM policy '{policy}'"),
+            (_, Some(lifecycle)) if lifecycle.enabled => match &lifecycle.data_retention {
+                Some(retention) => format!("data stream lifecycle, {retention} retention"),
+                None => "data stream lifecycle, unlimited retention".to_string(),
+            },
+            _ => "unmanaged".to_string(),
+        };
+
+        // Time series data streams have temporal ranges, even on versions that don't report the index mode
+        let index_mode = match (ds.index_mode, ds.time_series) {
+            (Some(mode), _) => mode,
+            (None, Some(_)) => "time_series".to_string(),
+            (None, None) => "standard".to_string(),
+        };
+
+        DataStreamDescription {
+            name: ds.name,
+            template: ds.template,
+            lifecycle,
+            generation: ds.generation,
+            backing_indices: ds.indices.len(),
+            health: ds.status.map(|s| s.to_lowercase()),
+            index_mode,
+        }
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Index resolution
+
+/// Maximum number of index names suggested for a missing index
+const MAX_SUGGESTIONS: usize = 3;
+
+async fn resolve(es_client: &Elasticsearch, name: &str) -> Result<ResolveIndexResponse, rmcp::Error> {
+    let response = es_client
+        .indices()
+        .resolve_index(IndicesResolveIndexParts::Name(&[name]))
+        .send()
+        .await;
+    read_json(response).await
+}
+
+#[derive(Deserialize)]
+struct ResolveIndexResponse {
+    #[serde(default)]
+    indices: Vec<ResolvedIndex>,
+    #[serde(default)]
+    aliases: Vec<ResolvedAlias>,
+    #[serde(default)]
+    data_streams: Vec<ResolvedDataStream>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ResolvedIndex {
+    name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attributes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_stream: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ResolvedAlias {
+    name: String,
+    #[serde(default)]
+    indices: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ResolvedDataStream {
+    name: String,
+    #[serde(default)]
+    backing_indices: Vec<String>,
+    timestamp_field: Option<String>,
+}
+
+/// Resolved names, with a backing index count for data streams as they can have many.
+#[derive(Serialize)]
+struct ResolvedNames {
+    indices: Vec<ResolvedIndex>,
+    aliases: Vec<ResolvedAlias>,
+    data_streams: Vec<DataStreamSummary>,
+}
+
+#[derive(Serialize)]
+struct DataStreamSummary {
+    name: String,
+    backing_indices: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp_field: Option<String>,
+}
+
+impl From<ResolveIndexResponse> for ResolvedNames {
+    fn from(response: ResolveIndexResponse) -> Self {
+        ResolvedNames {
+            indices: response.indices,
+            aliases: response.aliases,
+            data_streams: response
+                .data_streams
+                .into_iter()
+                .map(|ds| DataStreamSummary {
+                    name: ds.name,
+                    backing_indices: ds.backing_indices.len(),
+                    timestamp_field: ds.timestamp_field,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Find the existing indices, aliases and data streams whose name is closest to a missing index
+/// name or pattern. Returns an empty list if they can't be listed.
+pub(super) async fn suggest_indices(es_client: &Elasticsearch, missing: &str) -> Vec<String> {
+    let response = match resolve(es_client, "*").await {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::warn!("Failed to list indices for suggestions: {}", err.message);
+            return Vec::new();
+        }
+    };
+
+    let names = response
+        .indices
+        .iter()
+        .map(|i| i.name.as_str())
+        .chain(response.aliases.iter().map(|a| a.name.as_str()))
+        .chain(response.data_streams.iter().map(|ds| ds.name.as_str()));
+    closest_names(missing, names)
+}
+
+/// Find the names closest to `missing` by edit distance. For a pattern like `logz-*`, its literal
+/// prefix is compared to the prefixes of the names, and the suggestions are patterns like `logs-*`.
+fn closest_names<'a>(missing: &str, names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let (prefix, suffix) = match missing.find('*') {
+        Some(pos) => missing.split_at(pos),
+        None => (missing, ""),
+    };
+    if prefix.is_empty() {
+        return Vec::new();
+    }
+    let prefix_len = prefix.chars().count();
+    let max_distance = (prefix_len / 3).max(1);
+
+    // Distance, whether the suggestion's prefix ends differently, and the suggestion
+    let mut candidates: Vec<(usize, bool, String)> = Vec::new();
+    for name in names {
+        // Hidden and system indices are only relevant if asked for
+        if name.starts_with('.') && !missing.starts_with('.') {
+            continue;
+        }
+
+        let best = if suffix.is_empty() {
+            Some((edit_distance(prefix, name), false, name.to_string()))
+        } else {
+            // Compare with the name's prefixes of similar length, the closest lengths first, and
+            // prefer those ending like the pattern's prefix (e.g. `logs-*` rather than `logs*`)
+            let chars = name
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([name.len()])
+                .collect::<Vec<_>>();
+            let mut lengths = (0..chars.len()).collect::<Vec<_>>();
+            lengths.sort_by_key(|len| len.abs_diff(prefix_len));
+            lengths
+                .into_iter()
+                .filter(|len| len.abs_diff(prefix_len) <= max_distance)
+                .map(|len| {
+                    let candidate = &name[..chars[len]];
+                    let ends_alike = candidate.chars().last() == prefix.chars().last();
+                    (
+                        edit_distance(prefix, candidate),
+                        !ends_alike,
+                        format!("{candidate}{suffix}"),
+                    )
+                })
+                .min_by_key(|(distance, ends_differ, _)| (*distance, *ends_differ))
+        };
+
+        if let Some((distance, ends_differ, suggestion)) = best
+            && distance <= max_distance
+            && suggestion != missing
+            && !candidates.iter().any(|(_, _, s)| *s == suggestion)
+        {
+            candidates.push((distance, ends_differ, suggestion));
+        }
+    }
+
+    candidates.sort();
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, _, s)| s)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn describe_field_caps() {
+        let response: FieldCapsResponse = serde_json::from_value(json!({
+            "indices": ["logs-1", "logs-2"],
+            "fields": {
+                "_id": {"_id": {"type": "_id", "metadata_field": true, "searchable": true, "aggregatable": false}},
+                "service": {"object": {"type": "object", "metadata_field": false, "searchable": false, "aggregatable": false}},
+                "service.name": {"keyword": {"type": "keyword", "metadata_field": false, "searchable": true, "aggregatable": true}},
+                "status": {
+                    "keyword": {"type": "keyword", "searchable": true, "aggregatable": true, "indices": ["logs-1"]},
+                    "long": {"type": "long", "searchable": true, "aggregatable": true, "indices": ["logs-2"]}
+                },
+                "message": {
+                    "text": {"type": "text", "searchable": true, "aggregatable": false, "non_searchable_indices": ["logs-2"]}
+                }
+            }
+        }))
+        .unwrap();
+
+        let fields = describe(response);
+        assert_eq!(
+            vec!["message", "service.name", "status"],
+            fields.iter().map(|f| f.field.as_str()).collect::<Vec<_>>()
+        );
+
+        assert_eq!(Some(vec!["logs-2".to_string()]), fields[0].not_searchable_in);
+        assert_eq!("keyword", fields[1].type_);
+        assert!(fields[1].conflicts.is_none());
+
+        assert_eq!("keyword|long", fields[2].type_);
+        assert_eq!(
+            Some(BTreeMap::from([
+                ("keyword".to_string(), vec!["logs-1".to_string()]),
+                ("long".to_string(), vec!["logs-2".to_string()])
+            ])),
+            fields[2].conflicts
+        );
+    }
+
+    #[test]
+    fn profile_fields() {
+        let field = |name: &str, type_: &str, aggregatable: bool| FieldDescription {
+            field: name.to_string(),
+            type_: type_.to_string(),
+            searchable: true,
+            aggregatable,
+            conflicts: None,
+            not_searchable_in: None,
+            not_aggregatable_in: None,
+        };
+        let fields = vec![
+            field("service.name", "keyword", true),
+            field("@timestamp", "date", true),
+            field("message", "text", false),
+        ];
+
+        let aggs = profile_aggregations(&fields, 5);
+        assert_eq!(
+            vec!["0_cardinality", "0_terms", "1_cardinality", "1_max", "1_min"],
+            aggs.keys().collect::<Vec<_>>()
+        );
+
+        let results = IndexMap::from([
+            ("0_cardinality".to_string(), json!({"value": 2})),
+            (
+                "0_terms".to_string(),
+                json!({"buckets": [{"key": "api", "doc_count": 10}, {"key": "web", "doc_count": 5}]}),
+            ),
+            ("1_cardinality".to_string(), json!({"value": 100})),
+            (
+                "1_min".to_string(),
+                json!({"value": 0, "value_as_string": "1970-01-01T00:00:00.000Z"}),
+            ),
+            ("1_max".to_string(), json!({"value": null})),
+        ]);
+        let profiles = field_profiles(&fields, &results);
+
+        assert_eq!(
+            json!({
+                "type": "keyword",
+                "cardinality": 2,
+                "top_values": [{"value": "api", "count": 10}, {"value": "web", "count": 5}]
+            }),
+            profiles["service.name"]
+        );
+        assert_eq!(json!("1970-01-01T00:00:00.000Z"), profiles["@timestamp"]["min"]);
+        assert!(profiles["message"]["note"].is_string());
+    }
+
+    #[test]
+    fn index_suggestions() {
+        let names = [
+            "logs-nginx-default",
+            "logs-app-default",
+            "metrics-system-default",
+            ".kibana",
+            "log",
+        ];
+
+        assert_eq!(vec!["logs-*"], closest_names("logz-*", names.into_iter()));
+        assert_eq!(vec!["logs-*", "log*"], closest_names("log-*", names.into_iter()));
+        assert_eq!(
+            vec!["logs-app-default"],
+            closest_names("logs-app-defualt", names.into_iter())
+        );
+        assert_eq!(vec!["log"], closest_names("logz", names.into_iter()));
+        assert!(closest_names("traces-*", names.into_iter()).is_empty());
+    }
+
+    #[test]
+    fn describe_data_streams() {
+        let response: DataStreamsResponse = serde_json::from_value(json!({
+            "data_streams": [
+                {
+                    "name": "logs-nginx-default",
+                    "timestamp_field": {"name": "@timestamp"},
+                    "indices": [{"index_name": ".ds-logs-nginx-default-2025.01.01-000001"}],
+                    "generation": 1,
+                    "status": "GREEN",
+                    "template": "logs",
+                    "ilm_policy": "logs",
+                    "next_generation_managed_by": "Index Lifecycle Management"
+                },
+                {
+                    "name": "metrics-system-default",
+                    "indices": [],
+                    "generation": 3,
+                    "status": "YELLOW",
+                    "template": "metrics",
+                    "lifecycle": {"enabled": true, "data_retention": "7d"},
+                    "next_generation_managed_by": "Data stream lifecycle",
+                    "time_series": {"temporal_ranges": []}
+                }
+            ]
+        }))
+        .unwrap();
+
+        let descriptions = response
+            .data_streams
+            .into_iter()
+            .map(DataStreamDescription::from)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            json!([
+                {
+                    "name": "logs-nginx-default",
+                    "template": "logs",
+                    "lifecycle": "ILM policy 'logs'",
+                    "generation": 1,
+                    "backing_indices": 1,
+                    "health": "green",
+                    "index_mode": "standard"
+                },
+                {
+                    "name": "metrics-system-default",
+                    "template": "metrics",
+                    "lifecycle": "data stream lifecycle, 7d retention",
+                    "generation": 3,
+                    "backing_indices": 0,
+                    "health": "yellow",
+                    "index_mode": "time_series"
+                }
+            ]),
+            serde_json::to_value(descriptions).unwrap()
+        );
+    }
+}
@@ -0,0 +1,361 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Tools to get documents by id, so that LLMs don't need a search with an `ids` query.
+
+use crate::servers::elasticsearch::base_tools::{EsBaseTools, Hit, HitFormat, Hits, TotalHits};
+use crate::servers::elasticsearch::errors::ErrorCause;
+use crate::servers::elasticsearch::read_json;
+use elasticsearch::{Elasticsearch, MgetParts};
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+/// Maximum number of documents of a multi-get request
+const MAX_DOCUMENTS: usize = 100;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct GetDocumentParams {
+    /// Name of the index, alias or data stream containing the document
+    index: String,
+
+    /// Id of the document
+    id: String,
+
+    /// Routing value used when the document was indexed (optional)
+    routing: Option<String>,
+
+    #[serde(flatten)]
+    options: SourceOptions,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct MgetParams {
+    /// Index of the documents that don't have one (optional)
+    index: Option<String>,
+
+    /// Documents to get
+    docs: Vec<DocumentRef>,
+
+    #[serde(flatten)]
+    options: SourceOptions,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct DocumentRef {
+    /// Name of the index containing the document (optional, default: the `index` parameter)
+    index: Option<String>,
+
+    /// Id of the document
+    id: String,
+
+    /// Routing value used when the document was indexed (optional)
+    routing: Option<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize, schemars::JsonSchema)]
+struct SourceOptions {
+    /// Source fields to return, wildcards are supported (optional, default: all fields)
+    source_includes: Option<Vec<String>>,
+
+    /// Source fields to leave out, wildcards are supported (optional)
+    source_excludes: Option<Vec<String>>,
+
+    /// Stored fields to return. The source isn't returned when this is set, unless `source_includes` is set too
+    /// (optional)
+    stored_fields: Option<Vec<String>>,
+}
+
+#[tool_router(router = document_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: get document
+    #[tool(
+        description = "Get a document by id. Use this rather than a search when the index and id are known.",
+        annotations(title = "Get ES document", read_only_hint = true)
+    )]
+    async fn get_document(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(GetDocumentParams {
+            index,
+            id,
+            routing,
+            options,
+        }): Parameters<GetDocumentParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let docs = vec![DocumentRef {
+            index: Some(index),
+            id,
+            routing,
+        }];
+        let (found, missing) = multi_get(&es_client, None, docs, options).await?;
+
+        if let Some(doc) = missing.first() {
+            return Ok(CallToolResult::error(vec![Content::text(doc.describe())]));
+        }
+
+        let mut results = Vec::new();
+        self.push_hits_as("get_document", &found, HitFormat::Metadata, &mut results)?;
+        Ok(CallToolResult::success(results))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: multi-get
+    #[tool(
+        description = "Get several documents by id, from one or several indices. Documents that can't be found \
+            are listed separately.",
+        annotations(title = "Get ES documents", read_only_hint = true)
+    )]
+    async fn mget(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(MgetParams { index, docs, options }): Parameters<MgetParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        if docs.is_empty() || docs.len() > MAX_DOCUMENTS {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Between 1 and {MAX_DOCUMENTS} documents can be requested, got {}.",
+                docs.len()
+            ))]));
+        }
+        if index.is_none()
+            && let Some(doc) = docs.iter().find(|doc| doc.index.is_none())
+        {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Document [{}] has no index, and no default `index` was provided.",
+                doc.id
+            ))]));
+        }
+
+        let requested = docs.len();
+        let (found, missing) = multi_get(&es_client, index.as_deref(), docs, options).await?;
+
+        let mut results = vec![Content::text(format!(
+            "Found {} of {requested} documents.",
+            found.hits.len()
+        ))];
+        self.push_hits_as("mget", &found, HitFormat::Metadata, &mut results)?;
+        if !missing.is_empty() {
+            results.push(Content::text("Missing documents:"));
+            results.push(Content::json(&missing)?);
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Multi-get
+
+#[derive(Deserialize)]
+struct MgetResponse {
+    docs: Vec<MgetDoc>,
+}
+
+#[derive(Deserialize)]
+struct MgetDoc {
+    #[serde(default)]
+    found: bool,
+    error: Option<ErrorCause>,
+    #[serde(flatten)]
+    hit: Hit,
+}
+
+/// A document that couldn't be read.
+#[derive(Debug, PartialEq, Serialize)]
+struct MissingDocument {
+    #[serde(rename = "_index")]
+    index: String,
+    #[serde(rename = "_id")]
+    id: String,
+    found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl MissingDocument {
+    fn describe(&self) -> String {
+        match &self.error {
+            Some(error) => format!(
+                "Document [{}] of index [{}] can't be read: {error}",
+                self.id, self.index
+            ),
+            None => format!("Document [{}] not found in index [{}].", self.id, self.index),
+        }
+    }
+}
+
+/// Get documents, returning those that were found as hits, and those that weren't.
+async fn multi_get(
+    es_client: &Elasticsearch,
+    index: Option<&str>,
+    docs: Vec<DocumentRef>,
+    options: SourceOptions,
+) -> Result<(Hits, Vec<MissingDocument>), rmcp::Error> {
+    let parts = match index {
+        Some(index) => MgetParts::Index(index),
+        None => MgetParts::None,
+    };
+    let response = es_client.mget(parts).body(request_body(docs, &options)).send().await;
+    let response: MgetResponse = read_json(response).await?;
+
+    Ok(split_found(response, index))
+}
+
+fn request_body(docs: Vec<DocumentRef>, options: &SourceOptions) -> Value {
+    let mut source = Map::new();
+    if let Some(includes) = &options.source_includes {
+        source.insert("includes".to_string(), json!(includes));
+    }
+    if let Some(excludes) = &options.source_excludes {
+        source.insert("excludes".to_string(), json!(excludes));
+    }
+
+    let docs = docs
+        .into_iter()
+        .map(|doc| {
+            let mut value = Map::new();
+            value.insert("_id".to_string(), json!(doc.id));
+            if let Some(index) = doc.index {
+                value.insert("_index".to_string(), json!(index));
+            }
+            if let Some(routing) = doc.routing {
+                value.insert("routing".to_string(), json!(routing));
+            }
+            if !source.is_empty() {
+                value.insert("_source".to_string(), Value::Object(source.clone()));
+            }
+            if let Some(stored_fields) = &options.stored_fields {
+                value.insert("stored_fields".to_string(), json!(stored_fields));
+            }
+            Value::Object(value)
+        })
+        .collect::<Vec<_>>();
+
+    json!({ "docs": docs })
+}
+
+fn split_found(response: MgetResponse, index: Option<&str>) -> (Hits, Vec<MissingDocument>) {
+    let mut hits = Vec::new();
+    let mut missing = Vec::new();
+
+    for doc in response.docs {
+        if doc.found && doc.error.is_none() {
+            hits.push(doc.hit);
+            continue;
+        }
+        missing.push(MissingDocument {
+            index: doc.hit.index.or_else(|| index.map(str::to_string)).unwrap_or_default(),
+            id: doc.hit.id.unwrap_or_default(),
+            found: false,
+            error: doc.error.map(|e| match e.reason {
+                Some(reason) => format!("{}: {reason}", e.type_),
+                None => e.type_,
+            }),
+        });
+    }
+
+    let found = Hits {
+        total: Some(TotalHits {
+            value: hits.len() as u64,
+        }),
+        hits,
+    };
+    (found, missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request() {
+        let docs = vec![
+            DocumentRef {
+                index: None,
+                id: "1".to_string(),
+                routing: Some("user-1".to_string()),
+            },
+            DocumentRef {
+                index: Some("products".to_string()),
+                id: "2".to_string(),
+                routing: None,
+            },
+        ];
+        let options = SourceOptions {
+            source_includes: Some(vec!["name".to_string()]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            json!({"docs": [
+                {"_id": "1", "routing": "user-1", "_source": {"includes": ["name"]}},
+                {"_id": "2", "_index": "products", "_source": {"includes": ["name"]}}
+            ]}),
+            request_body(docs, &options)
+        );
+    }
+
+    #[test]
+    fn missing_documents() {
+        let response: MgetResponse = serde_json::from_value(json!({"docs": [
+            {"_index": "products", "_id": "1", "_version": 1, "found": true, "_source": {"name": "shoes"}},
+            {"_index": "products", "_id": "2", "found": false},
+            {"_index": "prodcts", "_id": "3", "error": {
+                "type": "index_not_found_exception",
+                "reason": "no such index [prodcts]",
+                "index": "prodcts"
+            }}
+        ]}))
+        .unwrap();
+
+        let (found, missing) = split_found(response, None);
+        assert_eq!(
+            vec![Some("1")],
+            found.hits.iter().map(|h| h.id.as_deref()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                MissingDocument {
+                    index: "products".to_string(),
+                    id: "2".to_string(),
+                    found: false,
+                    error: None,
+                },
+                MissingDocument {
+                    index: "prodcts".to_string(),
+                    id: "3".to_string(),
+                    found: false,
+                    error: Some("index_not_found_exception: no such index [prodcts]".to_string()),
+                },
+            ],
+            missing
+        );
+        assert_eq!(
+            "Document [3] of index [prodcts] can't be read: index_not_found_exception: no such index [prodcts]",
+            missing[1].describe()
+        );
+    }
+}
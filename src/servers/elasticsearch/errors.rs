@@ -0,0 +1,303 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Structured Elasticsearch errors.
+//!
+//! Error responses are parsed so that the client gets the error type, reason and causes instead
+//! of an opaque internal error. Client errors (4xx status codes), like an invalid query or a
+//! missing index, are returned as tool results with `is_error` set so that the LLM can correct
+//! its request, while server errors stay protocol errors.
+
+use rmcp::model::{CallToolResult, Content, ErrorCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+/// Key of the structured error in the `data` of an `rmcp::Error`
+const ES_ERROR_KEY: &str = "es_error";
+
+/// Maximum number of root causes and shard failures in the error message
+const MAX_ROOT_CAUSES: usize = 3;
+
+/// Status codes of errors that the LLM can correct by changing its request: bad request, not found
+/// and conflict. Authentication, authorization and back-pressure (429) errors aren't, and retrying
+/// immediately would make things worse.
+const CORRECTABLE_STATUSES: &[u64] = &[400, 404, 409];
+
+/// An Elasticsearch error response.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EsError {
+    #[serde(default)]
+    pub status: u16,
+    pub error: ErrorCause,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorCause {
+    #[serde(rename = "type")]
+    pub type_: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caused_by: Option<Box<ErrorCause>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub root_cause: Vec<ErrorCause>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_shards: Vec<ShardFailure>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShardFailure {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    pub reason: ErrorCause,
+}
+
+/// Some APIs return the error as a plain string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorBody {
+    Structured(EsError),
+    Text { error: String },
+}
+
+impl EsError {
+    /// Parse the body of an error response, falling back to a generic error if it isn't a
+    /// structured Elasticsearch error.
+    pub fn parse(status: u16, body: &str) -> EsError {
+        let (type_, reason) = match serde_json::from_str::<ErrorBody>(body) {
+            Ok(ErrorBody::Structured(mut error)) => {
+                error.status = status;
+                return error;
+            }
+            Ok(ErrorBody::Text { error }) => ("error".to_string(), error),
+            Err(_) => {
+                let reason = match body.trim() {
+                    "" => format!("request failed with status code {status}"),
+                    body => body.to_string(),
+                };
+                ("http_error".to_string(), reason)
+            }
+        };
+
+        EsError {
+            status,
+            error: ErrorCause {
+                type_,
+                reason: Some(reason),
+                index: None,
+                caused_by: None,
+                root_cause: Vec::new(),
+                failed_shards: Vec::new(),
+            },
+        }
+    }
+
+    /// Is this error caused by the request, and can it be corrected by the client?
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.status)
+    }
+
+    /// A concise, one-line description of the error, e.g. `index_not_found_exception: no such index [logz-*]`
+    pub fn message(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        let mut push = |cause: &ErrorCause| {
+            let text = cause.summary();
+            if !parts.contains(&text) {
+                parts.push(text);
+            }
+        };
+
+        // Shard failures have the actual cause of `search_phase_execution_exception`
+        let shard_causes = self.error.failed_shards.iter().map(|f| &f.reason);
+
+        let mut cause = Some(&self.error);
+        while let Some(c) = cause {
+            push(c);
+            cause = c.caused_by.as_deref();
+        }
+        for c in self.error.root_cause.iter().chain(shard_causes).take(MAX_ROOT_CAUSES) {
+            push(c);
+            let mut cause = c.caused_by.as_deref();
+            while let Some(c) = cause {
+                push(c);
+                cause = c.caused_by.as_deref();
+            }
+        }
+
+        parts.join("; caused by ")
+    }
+
+    /// Convert to an MCP error that keeps the structured error in its data.
+    pub fn into_rmcp_error(self) -> rmcp::Error {
+        let code = if self.is_client_error() {
+            ErrorCode::INVALID_REQUEST
+        } else {
+            ErrorCode::INTERNAL_ERROR
+        };
+        let message = self.message();
+        rmcp::Error::new(code, message, Some(json!({ ES_ERROR_KEY: self })))
+    }
+}
+
+impl ErrorCause {
+    fn summary(&self) -> String {
+        match (&self.reason, &self.index) {
+            (Some(reason), Some(index)) if !reason.contains(index.as_str()) => {
+                format!("{}: {} [{}]", self.type_, reason, index)
+            }
+            (Some(reason), _) => format!("{}: {}", self.type_, reason),
+            (None, _) => self.type_.clone(),
+        }
+    }
+}
+
+/// The missing index of an `index_not_found_exception` error.
+pub fn missing_index(err: &rmcp::Error) -> Option<&str> {
+    let error = err.data.as_ref()?.get(ES_ERROR_KEY)?.get("error")?;
+    if error.get("type")?.as_str()? != "index_not_found_exception" {
+        return None;
+    }
+    error.get("index")?.as_str()
+}
+
+/// Add suggested index names to an error, e.g. `no such index [logz-*]; did you mean logs-*?`
+pub fn with_suggestions(mut err: rmcp::Error, suggestions: Vec<String>) -> rmcp::Error {
+    if suggestions.is_empty() {
+        return err;
+    }
+
+    err.message = format!("{}; did you mean {}?", err.message, suggestions.join(" or ")).into();
+    if let Some(Value::Object(es_error)) = err.data.as_mut().and_then(|data| data.get_mut(ES_ERROR_KEY)) {
+        es_error.insert("suggestions".to_string(), json!(suggestions));
+    }
+    err
+}
+
+/// Convert an error for a client-side Elasticsearch error into an error result that the LLM can
+/// use to correct its request. Other errors, including client errors that can't be corrected by
+/// changing the request, are returned unchanged.
+pub fn into_tool_result(err: rmcp::Error) -> Result<CallToolResult, rmcp::Error> {
+    let Some(es_error) = err.data.as_ref().and_then(|data| data.get(ES_ERROR_KEY)) else {
+        return Err(err);
+    };
+
+    let is_correctable = es_error
+        .get("status")
+        .and_then(Value::as_u64)
+        .is_some_and(|status| CORRECTABLE_STATUSES.contains(&status));
+    if !is_correctable {
+        return Err(err);
+    }
+
+    let mut content = vec![Content::text(err.message.to_string())];
+    if let Ok(details) = Content::json(es_error) {
+        content.push(details);
+    }
+    Ok(CallToolResult::error(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_not_found() {
+        let error = EsError::parse(
+            404,
+            r#"{
+                "error": {
+                    "root_cause": [{"type": "index_not_found_exception", "reason": "no such index [logz-*]", "index": "logz-*"}],
+                    "type": "index_not_found_exception",
+                    "reason": "no such index [logz-*]",
+                    "index": "logz-*"
+                },
+                "status": 404
+            }"#,
+        );
+
+        assert!(error.is_client_error());
+        assert_eq!("index_not_found_exception: no such index [logz-*]", error.message());
+
+        let error = error.into_rmcp_error();
+        assert_eq!(Some("logz-*"), missing_index(&error));
+
+        let error = with_suggestions(error, vec!["logs-*".to_string()]);
+        assert_eq!(
+            "index_not_found_exception: no such index [logz-*]; did you mean logs-*?",
+            error.message
+        );
+
+        let result = into_tool_result(error).unwrap();
+        assert_eq!(Some(true), result.is_error);
+    }
+
+    #[test]
+    fn shard_failures() {
+        let error = EsError::parse(
+            400,
+            r#"{
+                "error": {
+                    "root_cause": [{"type": "query_shard_exception", "reason": "failed to create query: For input string: \"abc\"", "index": "logs"}],
+                    "type": "search_phase_execution_exception",
+                    "reason": "all shards failed",
+                    "failed_shards": [{
+                        "shard": 0,
+                        "index": "logs",
+                        "reason": {
+                            "type": "query_shard_exception",
+                            "reason": "failed to create query: For input string: \"abc\"",
+                            "index": "logs",
+                            "caused_by": {"type": "number_format_exception", "reason": "For input string: \"abc\""}
+                        }
+                    }]
+                },
+                "status": 400
+            }"#,
+        );
+
+        assert_eq!(
+            "search_phase_execution_exception: all shards failed; \
+            caused by query_shard_exception: failed to create query: For input string: \"abc\" [logs]; \
+            caused by number_format_exception: For input string: \"abc\"",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn unstructured_errors() {
+        let error = EsError::parse(500, "");
+        assert_eq!("http_error: request failed with status code 500", error.message());
+        assert!(into_tool_result(error.into_rmcp_error()).is_err());
+
+        let error = EsError::parse(400, r#"{"error": "bad request"}"#);
+        assert_eq!("error: bad request", error.message());
+    }
+
+    #[test]
+    fn uncorrectable_client_errors() {
+        for status in [401, 403, 429] {
+            let error = EsError::parse(status, r#"{"error": {"type": "security_exception"}}"#);
+            assert!(into_tool_result(error.into_rmcp_error()).is_err());
+        }
+        let error = EsError::parse(409, r#"{"error": {"type": "version_conflict_engine_exception"}}"#);
+        assert!(into_tool_result(error.into_rmcp_error()).is_ok());
+    }
+}
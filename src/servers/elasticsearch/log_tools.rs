@@ -0,0 +1,289 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Log triage tools, that summarize logs instead of returning log lines.
+
+use crate::servers::elasticsearch::analytics_tools::filtered_query;
+use crate::servers::elasticsearch::base_tools::{EsBaseTools, SearchResult};
+use crate::servers::elasticsearch::read_json;
+use elasticsearch::SearchParts;
+use indexmap::IndexMap;
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde_json::{Map, Value, json};
+
+const DEFAULT_LOG_TIME_RANGE: &str = "now-1h";
+const DEFAULT_MESSAGE_FIELD: &str = "message";
+const DEFAULT_SPLIT_FIELD: &str = "service.name";
+const TIME_FIELD: &str = "@timestamp";
+
+const DEFAULT_PATTERNS: u32 = 20;
+const MAX_PATTERNS: u32 = 100;
+
+/// Number of services listed for each pattern
+const SERVICES_PER_PATTERN: u32 = 5;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct LogPatternsParams {
+    /// Index pattern for log data (e.g., "logs-*")
+    index_pattern: String,
+
+    /// Time range of the logs (e.g., "now-1h", "now-1d", "2024-01-01T00:00:00Z,2024-01-01T23:59:59Z")
+    /// (optional, default: "now-1h")
+    time_range: Option<String>,
+
+    /// Log level to filter by (optional)
+    log_level: Option<String>,
+
+    /// Service name to filter by (optional)
+    service_name: Option<String>,
+
+    /// Additional filters as a Query DSL query (optional)
+    query: Option<Map<String, Value>>,
+
+    /// `text` field containing the log messages (optional, default: "message")
+    message_field: Option<String>,
+
+    /// Field by which the counts of each pattern are split (optional, default: "service.name")
+    split_by: Option<String>,
+
+    /// Maximum number of patterns, the most frequent first (optional, default: 20, max: 100)
+    size: Option<u32>,
+}
+
+#[tool_router(router = log_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: log patterns
+    #[tool(
+        description = "Group log messages into patterns, to see what kinds of logs or errors there are. Returns \
+            the most frequent patterns with their count, an example message, when they were first and last \
+            seen, and the services that logged them.",
+        annotations(title = "Log patterns", read_only_hint = true)
+    )]
+    async fn log_patterns(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(LogPatternsParams {
+            index_pattern,
+            time_range,
+            log_level,
+            service_name,
+            query,
+            message_field,
+            split_by,
+            size,
+        }): Parameters<LogPatternsParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let time_range = time_range.as_deref().unwrap_or(DEFAULT_LOG_TIME_RANGE);
+        let mut filters = Vec::new();
+        if let Some(log_level) = log_level {
+            filters.push(json!({ "term": { "log.level": log_level } }));
+        }
+        if let Some(service_name) = service_name {
+            filters.push(json!({ "term": { "service.name": service_name } }));
+        }
+        filters.extend(query.map(Value::Object));
+        let query = (!filters.is_empty()).then(|| Map::from_iter([("bool".to_string(), json!({ "filter": filters }))]));
+        let query = filtered_query(query, Some(time_range), TIME_FIELD)?;
+
+        let patterns = LogPatterns {
+            message_field: message_field.unwrap_or_else(|| DEFAULT_MESSAGE_FIELD.to_string()),
+            split_by: split_by.unwrap_or_else(|| DEFAULT_SPLIT_FIELD.to_string()),
+            size: size.unwrap_or(DEFAULT_PATTERNS).clamp(1, MAX_PATTERNS),
+        };
+
+        let response = es_client
+            .search(SearchParts::Index(&[&index_pattern]))
+            .body(patterns.request(query))
+            .send()
+            .await;
+        let response: SearchResult = read_json(response).await?;
+
+        let total = response.hits.total.as_ref().map_or(0, |t| t.value);
+        let rows = patterns.rows(&response.aggregations);
+        let mut results = vec![Content::text(format!(
+            "{} patterns in {total} log messages of {index_pattern} ({time_range}), the most frequent first:",
+            rows.len().saturating_sub(1)
+        ))];
+        self.push_rows("log_patterns", rows, &mut results)?;
+
+        Ok(CallToolResult::success(results))
+    }
+}
+
+struct LogPatterns {
+    message_field: String,
+    split_by: String,
+    size: u32,
+}
+
+impl LogPatterns {
+    fn request(&self, query: Value) -> Map<String, Value> {
+        let mut body = Map::new();
+        body.insert("size".to_string(), json!(0));
+        body.insert("track_total_hits".to_string(), json!(true));
+        body.insert("query".to_string(), query);
+        body.insert(
+            "aggs".to_string(),
+            json!({
+                "patterns": {
+                    "categorize_text": { "field": self.message_field, "size": self.size },
+                    "aggs": {
+                        "example": {
+                            "top_hits": {
+                                "size": 1,
+                                "_source": false,
+                                "fields": [self.message_field],
+                                "sort": [{ TIME_FIELD: "desc" }]
+                            }
+                        },
+                        "first_seen": { "min": { "field": TIME_FIELD } },
+                        "last_seen": { "max": { "field": TIME_FIELD } },
+                        "split": { "terms": { "field": self.split_by, "size": SERVICES_PER_PATTERN } }
+                    }
+                }
+            }),
+        );
+        body
+    }
+
+    /// Table of patterns, with the column names in the first row.
+    fn rows(&self, aggregations: &IndexMap<String, Value>) -> Vec<Value> {
+        let mut rows = vec![json!([
+            "count",
+            "pattern",
+            "example",
+            "first_seen",
+            "last_seen",
+            self.split_by
+        ])];
+
+        let buckets = aggregations
+            .get("patterns")
+            .and_then(|patterns| patterns.get("buckets"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten();
+        for bucket in buckets {
+            let example = bucket
+                .pointer("/example/hits/hits/0/fields")
+                .and_then(|fields| fields.get(&self.message_field))
+                .and_then(|values| values.get(0))
+                .cloned()
+                .unwrap_or_default();
+            let seen = |name: &str| {
+                let agg = bucket.get(name);
+                agg.and_then(|a| a.get("value_as_string"))
+                    .or_else(|| agg.and_then(|a| a.get("value")))
+                    .cloned()
+                    .unwrap_or_default()
+            };
+
+            rows.push(json!([
+                bucket.get("doc_count").cloned().unwrap_or_default(),
+                bucket.get("key").cloned().unwrap_or_default(),
+                example,
+                seen("first_seen"),
+                seen("last_seen"),
+                split_counts(bucket.get("split")),
+            ]));
+        }
+        rows
+    }
+}
+
+/// Counts of a terms aggregation as a string, e.g. `frontend (120), cart (3), 5 other documents`.
+fn split_counts(terms: Option<&Value>) -> String {
+    let Some(terms) = terms else {
+        return String::new();
+    };
+    let mut counts = terms
+        .get("buckets")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|bucket| {
+            let key = match bucket.get("key") {
+                Some(Value::String(key)) => key.clone(),
+                Some(key) => key.to_string(),
+                None => String::new(),
+            };
+            let count = bucket.get("doc_count").and_then(Value::as_u64).unwrap_or_default();
+            format!("{key} ({count})")
+        })
+        .collect::<Vec<_>>();
+
+    let others = terms
+        .get("sum_other_doc_count")
+        .and_then(Value::as_u64)
+        .unwrap_or_default();
+    match others {
+        0 => {}
+        1 => counts.push("1 other document".to_string()),
+        _ => counts.push(format!("{others} other documents")),
+    }
+    counts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_rows() {
+        let patterns = LogPatterns {
+            message_field: "message".to_string(),
+            split_by: "service.name".to_string(),
+            size: DEFAULT_PATTERNS,
+        };
+        let aggregations: IndexMap<String, Value> = serde_json::from_value(json!({
+            "patterns": {"buckets": [{
+                "doc_count": 123,
+                "key": "Connection refused to host",
+                "example": {"hits": {"hits": [{"fields": {"message": ["Connection refused to host db-1:5432"]}}]}},
+                "first_seen": {"value": 1735725600000.0, "value_as_string": "2025-01-01T10:00:00.000Z"},
+                "last_seen": {"value": 1735729200000.0, "value_as_string": "2025-01-01T11:00:00.000Z"},
+                "split": {
+                    "sum_other_doc_count": 3,
+                    "buckets": [{"key": "checkout", "doc_count": 100}, {"key": "cart", "doc_count": 20}]
+                }
+            }]}
+        }))
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                json!(["count", "pattern", "example", "first_seen", "last_seen", "service.name"]),
+                json!([
+                    123,
+                    "Connection refused to host",
+                    "Connection refused to host db-1:5432",
+                    "2025-01-01T10:00:00.000Z",
+                    "2025-01-01T11:00:00.000Z",
+                    "checkout (100), cart (20), 3 other documents"
+                ]),
+            ],
+            patterns.rows(&aggregations)
+        );
+    }
+}
@@ -15,9 +15,26 @@
 // specific language governing permissions and limitations
 // under the License.
 
+mod admin_tools;
+mod analytics_tools;
+mod apm_tools;
+mod async_tools;
+mod audit;
 mod base_tools;
+mod budget;
+mod caller;
+mod cluster_tools;
+mod discovery_tools;
+mod document_tools;
+mod errors;
+mod log_tools;
+mod pagination;
+mod query_tools;
+mod rate_limit;
+mod semantic_tools;
 
 use crate::servers::IncludeExclude;
+use crate::servers::elasticsearch::errors::EsError;
 use crate::utils::none_if_empty_string;
 use elasticsearch::Elasticsearch;
 use elasticsearch::auth::Credentials;
@@ -26,16 +43,24 @@ use elasticsearch::http::Url;
 use elasticsearch::http::response::Response;
 use http::header::USER_AGENT;
 use http::request::Parts;
-use http::{HeaderValue, header};
+use http::{HeaderName, HeaderValue, StatusCode, header};
 use indexmap::IndexMap;
 use rmcp::RoleServer;
 use rmcp::model::ToolAnnotations;
 use rmcp::service::RequestContext;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_aux::field_attributes::deserialize_bool_from_anything;
+use serde_aux::field_attributes::{
+    deserialize_bool_from_anything, deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// `X-Opaque-Id` header sent with all requests, to identify the tasks started by this server
+const OPAQUE_ID_HEADER: &str = "x-opaque-id";
+const OPAQUE_ID: &str = "elastic-mcp";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ElasticsearchMcpConfig {
@@ -58,6 +83,27 @@ pub struct ElasticsearchMcpConfig {
     #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
     pub ssl_skip_verify: bool,
 
+    /// Enable the `admin` tool group (index, mapping, template, alias and ILM management, task cancellation).
+    /// These tools modify the cluster and are disabled by default.
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub admin_tools: bool,
+
+    /// Audit log of tool invocations
+    #[serde(default)]
+    pub audit: Option<AuditConfig>,
+
+    /// Rate and concurrency limits of tool calls, per caller
+    #[serde(default)]
+    pub limits: Option<LimitsConfig>,
+
+    /// Addresses of the reverse proxies whose `X-Forwarded-For` header is used as the client address
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// Size limits of tool results, so that they fit in the LLM context window
+    #[serde(default)]
+    pub output: OutputConfig,
+
     /// Search templates to expose as tools or resources
     #[serde(default)]
     pub tools: Tools,
@@ -171,6 +217,139 @@ pub enum SearchTemplate {
     Template(serde_json::Value), // or constrain to an object?
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// Where audit records are written
+    pub sink: AuditSink,
+
+    /// Additional argument names whose values are redacted. Arguments with names like
+    /// `password`, `token` or `api_key` are always redacted.
+    #[serde(default)]
+    pub redact: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditSink {
+    /// JSON Lines written to stderr
+    Stderr,
+    /// JSON Lines written to a file
+    File(FileAuditSink),
+    /// Documents written to an Elasticsearch index, using the server's credentials
+    Elasticsearch(EsAuditSink),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileAuditSink {
+    pub path: PathBuf,
+
+    /// Rotate the file when it exceeds this size, in megabytes
+    #[serde(
+        default = "default_audit_max_size_mb",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub max_size_mb: u64,
+
+    /// Number of rotated files to keep
+    #[serde(
+        default = "default_audit_max_files",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub max_files: usize,
+
+    /// Delete rotated files older than this number of days
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_age_days: Option<u64>,
+}
+
+fn default_audit_max_size_mb() -> u64 {
+    100
+}
+
+fn default_audit_max_files() -> usize {
+    5
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EsAuditSink {
+    /// Index (or data stream) name
+    pub index: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LimitsConfig {
+    /// Limits of all the tool calls of a caller
+    #[serde(flatten)]
+    pub default: Limits,
+
+    /// Limits of the calls of a caller to specific tools, in addition to the global limits
+    #[serde(default)]
+    pub tools: HashMap<String, Limits>,
+
+    /// How callers are identified
+    #[serde(default)]
+    pub key_by: RateLimitKey,
+}
+
+/// How callers are identified by rate limits
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    /// Client IP address: callers behind the same NAT or proxy share their limits
+    #[default]
+    Ip,
+    /// User of the `Authorization` header, or IP address if there's none. Credentials aren't checked
+    /// before rate limiting, so a client can get new limits by sending different credentials.
+    User,
+    /// Both the user and the IP address
+    UserIp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Limits {
+    /// Sustained number of calls per second
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub rate: Option<f64>,
+
+    /// Maximum burst of calls. Defaults to one second's worth of calls
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub burst: Option<u32>,
+
+    /// Maximum number of concurrent calls
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_in_flight: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct OutputConfig {
+    /// Limits of all tool results
+    #[serde(flatten)]
+    pub default: OutputLimits,
+
+    /// Limits of specific tools, overriding the global limits
+    #[serde(default)]
+    pub tools: HashMap<String, OutputLimits>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct OutputLimits {
+    /// Maximum size of a tool result, in bytes
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_bytes: Option<usize>,
+
+    /// Maximum size of a tool result, in approximate tokens (default: 20000)
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_tokens: Option<usize>,
+
+    /// Strings longer than this number of characters are truncated (default: 2000)
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_string_length: Option<usize>,
+
+    /// Arrays longer than this are shortened (default: 100)
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub max_array_length: Option<usize>,
+}
+
 #[derive(Clone)]
 pub struct ElasticsearchMcp {}
 
@@ -207,10 +386,15 @@ impl ElasticsearchMcp {
             USER_AGENT,
             HeaderValue::from_str(&format!("elastic-mcp/{}", env!("CARGO_PKG_VERSION")))?,
         );
+        // Identifies the tasks started by this server in the tasks API
+        transport = transport.header(
+            HeaderName::from_static(OPAQUE_ID_HEADER),
+            HeaderValue::from_static(OPAQUE_ID),
+        );
         let transport = transport.build()?;
         let es_client = Elasticsearch::new(transport);
 
-        Ok(base_tools::EsBaseTools::new(es_client))
+        base_tools::EsBaseTools::new(es_client, &config)
     }
 }
 
@@ -250,21 +434,30 @@ pub fn internal_error(e: impl std::error::Error) -> rmcp::Error {
     rmcp::Error::internal_error(e.to_string(), None)
 }
 
-/// Return an error as an error response to the client, which may be able to take
-/// action to correct it. This should be refined to handle common error types such
-/// as index not found, which could be caused by the client hallucinating an index name.
-///
-/// TODO (in rmcp): if rmcp::Error had a variant that accepts a CallToolResult, this would
-/// allow to use the '?' operator while sending a result to the client.
-pub fn handle_error(result: Result<Response, elasticsearch::Error>) -> Result<Response, rmcp::Error> {
-    match result {
-        Ok(resp) => resp.error_for_status_code(),
+/// Check the response status, converting Elasticsearch error responses to structured errors.
+/// Client errors, such as an index not found, are sent to the client as error results by the
+/// tool call handler (see `errors::into_tool_result`) so that it can correct its request.
+pub async fn handle_error(result: Result<Response, elasticsearch::Error>) -> Result<Response, rmcp::Error> {
+    let resp = match result {
+        Ok(resp) => resp,
         Err(e) => {
             tracing::error!("Error: {:?}", &e);
-            Err(e)
+            return Err(internal_error(e));
         }
+    };
+
+    audit::record_es_request(&resp);
+    let status = resp.status_code();
+    if status.is_success() {
+        return Ok(resp);
+    }
+
+    let body = resp.text().await.unwrap_or_default();
+    let error = EsError::parse(status.as_u16(), &body);
+    if !error.is_client_error() {
+        tracing::error!("Elasticsearch error: {}", error.message());
     }
-    .map_err(internal_error)
+    Err(error.into_rmcp_error())
 }
 
 pub async fn read_json<T: DeserializeOwned>(
@@ -274,12 +467,24 @@ pub async fn read_json<T: DeserializeOwned>(
     // tracing::debug!("Received json {text}");
     // serde_json::from_str(&text).map_err(internal_error)
 
-    let response = handle_error(response)?;
+    let response = handle_error(response).await?;
     response.json().await.map_err(internal_error)
 }
 
-#[allow(dead_code)]
+/// Read a JSON response, returning `None` if the resource doesn't exist (404 status code).
+pub async fn read_json_opt<T: DeserializeOwned>(
+    response: Result<Response, elasticsearch::Error>,
+) -> Result<Option<T>, rmcp::Error> {
+    if let Ok(resp) = &response
+        && resp.status_code() == StatusCode::NOT_FOUND
+    {
+        audit::record_es_request(resp);
+        return Ok(None);
+    }
+    read_json(response).await.map(Some)
+}
+
 pub async fn read_text(result: Result<Response, elasticsearch::Error>) -> Result<String, rmcp::Error> {
-    let response = handle_error(result)?;
+    let response = handle_error(result).await?;
     response.text().await.map_err(internal_error)
 }
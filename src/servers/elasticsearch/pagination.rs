@@ -0,0 +1,427 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cursor-based pagination of search and ES|QL results.
+//!
+//! Searches open a point in time (PIT) before reading their first page, so that all pages see the
+//! same data. Pages are sorted with a `_shard_doc` tiebreaker and return a cursor containing the
+//! PIT id and the sort values of the last hit sent, that the next call uses as `search_after`.
+//! Composite aggregations return a cursor containing their `after_key`. ES|QL queries run
+//! asynchronously and return a cursor containing the query id if they're still running.
+//!
+//! Cursors are opaque to the caller (base64-encoded JSON) and bound to the query they were created
+//! for. PITs and async results expire on the Elasticsearch side after [`KEEP_ALIVE`] if the cursor
+//! isn't used, and are released as soon as the last page has been fetched.
+
+use crate::servers::elasticsearch::base_tools::{EsqlQueryRequest, EsqlQueryResponse, HitFormat, SearchResult};
+use crate::servers::elasticsearch::{handle_error, read_json, read_json_opt};
+use base64::prelude::*;
+use elasticsearch::esql::{EsqlAsyncQueryDeleteParts, EsqlAsyncQueryGetParts};
+use elasticsearch::{Elasticsearch, OpenPointInTimeParts, SearchParts};
+use rmcp::model::Content;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// How long PITs and async ES|QL results are kept between two calls
+pub const KEEP_ALIVE: &str = "5m";
+
+/// How long an ES|QL query can run before returning a cursor
+const ESQL_WAIT_TIMEOUT: &str = "30s";
+
+/// Default `size` of Elasticsearch searches
+const DEFAULT_SIZE: u64 = 10;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Cursor {
+    /// Next page of a search, following the hit whose sort values are `search_after`
+    Search {
+        pit: String,
+        search_after: Vec<Value>,
+        /// Format of the hits of the first page
+        format: HitFormat,
+        query: u64,
+    },
+    /// Results of a running ES|QL query
+    Esql { id: String, query: u64 },
+    /// Next buckets of a composite aggregation
+    Composite { after: Value, query: u64 },
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        BASE64_STANDARD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(cursor: &str) -> Result<Self, rmcp::Error> {
+        BASE64_STANDARD
+            .decode(cursor.trim())
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| rmcp::Error::invalid_params("Invalid cursor", None))
+    }
+}
+
+/// Content returned to the caller with the cursor of the next page.
+pub fn next_cursor_content(cursor: &str) -> Result<Content, rmcp::Error> {
+    Content::json(json!({ "next_cursor": cursor }))
+}
+
+fn query_hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn mismatch() -> rmcp::Error {
+    rmcp::Error::invalid_params("The cursor was created for a different query", None)
+}
+
+fn expired() -> rmcp::Error {
+    rmcp::Error::invalid_params("The cursor has expired: run the query again without a cursor", None)
+}
+
+//-------------------------------------------------------------------------------------------------
+// Search
+
+/// Pagination state of a search response.
+pub struct SearchPager {
+    query: u64,
+    size: usize,
+    paginated: bool,
+    format: HitFormat,
+}
+
+/// Run a search on `index`, continuing from `cursor` if provided.
+///
+/// The first page opens a point in time, follow-up pages run in the point in time of the cursor.
+/// Aggregation-only searches (`size` is zero) aren't paginated and run without a point in time.
+///
+/// The hit `format` of the first page is kept in the cursor, so that all pages have the same format.
+pub async fn search(
+    es_client: &Elasticsearch,
+    index: &str,
+    mut query_body: Map<String, Value>,
+    cursor: Option<&str>,
+    format: HitFormat,
+) -> Result<(SearchResult, SearchPager), rmcp::Error> {
+    let query = query_hash(&(index, serde_json::to_string(&query_body).unwrap_or_default()));
+    let size = query_body.get("size").and_then(Value::as_u64).unwrap_or(DEFAULT_SIZE) as usize;
+    let mut pager = SearchPager {
+        query,
+        size,
+        paginated: size > 0,
+        format,
+    };
+
+    let pit = match cursor {
+        None if size == 0 => {
+            let response = es_client
+                .search(SearchParts::Index(&[index]))
+                .body(query_body)
+                .send()
+                .await;
+            return Ok((read_json(response).await?, pager));
+        }
+        None => {
+            let response = es_client
+                .open_point_in_time(OpenPointInTimeParts::Index(&[index]))
+                .keep_alive(KEEP_ALIVE)
+                .send()
+                .await;
+            read_json::<PitResponse>(response).await?.id
+        }
+        Some(cursor) => {
+            let Cursor::Search {
+                pit,
+                search_after,
+                format,
+                query: cursor_query,
+            } = Cursor::decode(cursor)?
+            else {
+                return Err(mismatch());
+            };
+            if cursor_query != query || size == 0 {
+                return Err(mismatch());
+            }
+
+            // search_after replaces from-based pagination
+            query_body.remove("from");
+            query_body.insert("search_after".to_string(), Value::Array(search_after));
+            pager.format = format;
+            pit
+        }
+    };
+
+    query_body.insert("pit".to_string(), json!({ "id": pit, "keep_alive": KEEP_ALIVE }));
+    add_tiebreaker(&mut query_body);
+
+    // PIT searches must not have a target index
+    let response = es_client.search(SearchParts::None).body(query_body).send().await;
+    let response = match cursor {
+        None => read_json(response).await.map(Some),
+        Some(_) => read_json_opt::<SearchResult>(response).await,
+    };
+    match response {
+        Ok(Some(response)) => Ok((response, pager)),
+        // The point in time of the cursor has expired
+        Ok(None) => Err(expired()),
+        Err(err) => {
+            close_pit(es_client, pit).await;
+            Err(err)
+        }
+    }
+}
+
+/// Sort values are needed for `search_after`: sort by `_score` if the query has no sort, and add
+/// a `_shard_doc` tiebreaker so that hits with the same sort values aren't skipped or repeated.
+fn add_tiebreaker(query_body: &mut Map<String, Value>) {
+    let mut sort = match query_body.remove("sort") {
+        Some(Value::Array(sort)) => sort,
+        Some(sort) => vec![sort],
+        None => vec![json!("_score")],
+    };
+    let is_tiebreaker = |field: &Value| field.as_str() == Some("_shard_doc") || field.get("_shard_doc").is_some();
+    if !sort.iter().any(is_tiebreaker) {
+        sort.push(json!({ "_shard_doc": "asc" }));
+    }
+    query_body.insert("sort".to_string(), Value::Array(sort));
+}
+
+impl SearchPager {
+    /// Format of the hits of the page.
+    pub fn format(&self) -> HitFormat {
+        self.format
+    }
+
+    /// The cursor of the page that follows the first `shown` hits of `response`.
+    ///
+    /// If there are no more hits, returns `None` and closes the point in time.
+    pub async fn next_cursor(
+        &self,
+        es_client: &Elasticsearch,
+        response: &SearchResult,
+        shown: usize,
+    ) -> Result<Option<String>, rmcp::Error> {
+        if !self.paginated {
+            return Ok(None);
+        }
+
+        let Some(pit) = response.pit_id.clone() else {
+            return Ok(None);
+        };
+        let hits = &response.hits.hits;
+        // A full page may be followed by more hits
+        let more = shown > 0 && (shown < hits.len() || hits.len() >= self.size);
+        let search_after = shown.checked_sub(1).and_then(|last| hits[last].sort.clone());
+        match search_after {
+            Some(search_after) if more => Ok(Some(
+                Cursor::Search {
+                    pit,
+                    search_after,
+                    format: self.format,
+                    query: self.query,
+                }
+                .encode(),
+            )),
+            _ => {
+                close_pit(es_client, pit).await;
+                Ok(None)
+            }
+        }
+    }
+}
+
+async fn close_pit(es_client: &Elasticsearch, pit: String) {
+    let response = es_client.close_point_in_time().body(json!({ "id": pit })).send().await;
+    if let Err(err) = handle_error(response).await {
+        // Not critical: the PIT will expire
+        tracing::warn!("Failed to close point in time: {}", err.message);
+    }
+}
+
+#[derive(Deserialize)]
+struct PitResponse {
+    id: String,
+}
+
+//-------------------------------------------------------------------------------------------------
+// Composite aggregations
+
+/// Run an aggregation-only search whose aggregation `name` is a composite aggregation, continuing
+/// after the buckets of `cursor` if provided. Returns the response and the cursor of the next
+/// buckets, if there may be more.
+pub async fn composite_search(
+    es_client: &Elasticsearch,
+    index: &str,
+    mut query_body: Map<String, Value>,
+    name: &str,
+    cursor: Option<&str>,
+) -> Result<(SearchResult, Option<String>), rmcp::Error> {
+    let query = query_hash(&(index, serde_json::to_string(&query_body).unwrap_or_default()));
+
+    let composite = query_body
+        .get_mut("aggs")
+        .and_then(|aggs| aggs.get_mut(name))
+        .and_then(|agg| agg.get_mut("composite"))
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| rmcp::Error::internal_error(format!("No composite aggregation named '{name}'"), None))?;
+    let size = composite.get("size").and_then(Value::as_u64).unwrap_or(DEFAULT_SIZE) as usize;
+
+    match cursor.map(Cursor::decode).transpose()? {
+        Some(Cursor::Composite {
+            after,
+            query: cursor_query,
+        }) => {
+            if cursor_query != query {
+                return Err(mismatch());
+            }
+            composite.insert("after".to_string(), after);
+        }
+        Some(_) => return Err(mismatch()),
+        None => {}
+    }
+
+    query_body.insert("size".to_string(), json!(0));
+    let response = es_client
+        .search(SearchParts::Index(&[index]))
+        .body(query_body)
+        .send()
+        .await;
+    let response: SearchResult = read_json(response).await?;
+
+    let aggregation = response.aggregations.get(name);
+    let buckets = aggregation.and_then(|agg| agg.get("buckets")).and_then(Value::as_array);
+    let after_key = aggregation.and_then(|agg| agg.get("after_key"));
+    let cursor = match (buckets, after_key) {
+        // A full page may be followed by more buckets
+        (Some(buckets), Some(after)) if buckets.len() >= size => Some(
+            Cursor::Composite {
+                after: after.clone(),
+                query,
+            }
+            .encode(),
+        ),
+        _ => None,
+    };
+
+    Ok((response, cursor))
+}
+
+//-------------------------------------------------------------------------------------------------
+// ES|QL
+
+/// Run an ES|QL query asynchronously, or get the results of a running query if `cursor` is
+/// provided. Returns the response and a cursor if the query is still running.
+pub async fn esql(
+    es_client: &Elasticsearch,
+    mut request: EsqlQueryRequest,
+    cursor: Option<&str>,
+) -> Result<(EsqlQueryResponse, Option<String>), rmcp::Error> {
+    let query = query_hash(&request.query);
+
+    let response: EsqlQueryResponse = match cursor.map(Cursor::decode).transpose()? {
+        Some(Cursor::Esql {
+            id,
+            query: cursor_query,
+        }) => {
+            if cursor_query != query {
+                return Err(mismatch());
+            }
+            let response = es_client
+                .esql()
+                .async_query_get(EsqlAsyncQueryGetParts::Id(&id))
+                .wait_for_completion_timeout(ESQL_WAIT_TIMEOUT)
+                .keep_alive(KEEP_ALIVE)
+                .send()
+                .await;
+            read_json_opt(response).await?.ok_or_else(expired)?
+        }
+        Some(_) => return Err(mismatch()),
+        None => {
+            request.wait_for_completion_timeout = Some(ESQL_WAIT_TIMEOUT.to_string());
+            request.keep_alive = Some(KEEP_ALIVE.to_string());
+            let response = es_client.esql().async_query().body(request).send().await;
+            read_json(response).await?
+        }
+    };
+
+    match (&response.id, response.is_running) {
+        (Some(id), true) => {
+            let cursor = Cursor::Esql { id: id.clone(), query }.encode();
+            Ok((response, Some(cursor)))
+        }
+        (Some(id), false) => {
+            // Results have been fetched, they're no more needed
+            let result = es_client
+                .esql()
+                .async_query_delete(EsqlAsyncQueryDeleteParts::Id(id))
+                .send()
+                .await;
+            if let Err(err) = handle_error(result).await {
+                tracing::warn!("Failed to delete async ES|QL results: {}", err.message);
+            }
+            Ok((response, None))
+        }
+        (None, _) => Ok((response, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_encoding() {
+        let cursor = Cursor::Search {
+            pit: "pit-id".to_string(),
+            search_after: vec![json!(1700000000000u64), json!(42)],
+            format: HitFormat::Source,
+            query: u64::MAX,
+        };
+        assert_eq!(cursor, Cursor::decode(&cursor.encode()).unwrap());
+
+        let cursor = Cursor::Composite {
+            after: json!({"host": "web-1"}),
+            query: 42,
+        };
+        assert_eq!(cursor, Cursor::decode(&cursor.encode()).unwrap());
+
+        assert!(Cursor::decode("not a cursor").is_err());
+        assert!(Cursor::decode(&BASE64_STANDARD.encode(b"{\"type\": \"other\"}")).is_err());
+    }
+
+    #[test]
+    fn tiebreaker() {
+        let sorted = |body: Value| {
+            let mut body = body.as_object().unwrap().clone();
+            add_tiebreaker(&mut body);
+            body["sort"].clone()
+        };
+
+        assert_eq!(json!(["_score", { "_shard_doc": "asc" }]), sorted(json!({})));
+        assert_eq!(
+            json!([{ "@timestamp": "desc" }, { "_shard_doc": "asc" }]),
+            sorted(json!({ "sort": { "@timestamp": "desc" } }))
+        );
+        assert_eq!(
+            json!(["host.name", { "_shard_doc": "desc" }]),
+            sorted(json!({ "sort": ["host.name", { "_shard_doc": "desc" }] }))
+        );
+    }
+}
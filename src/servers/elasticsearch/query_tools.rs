@@ -0,0 +1,336 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Query validation and explain tools, so that the LLM can fix its queries.
+
+use crate::servers::elasticsearch::base_tools::EsBaseTools;
+use crate::servers::elasticsearch::errors::EsError;
+use crate::servers::elasticsearch::{audit, internal_error, read_json};
+use elasticsearch::indices::IndicesValidateQueryParts;
+use elasticsearch::{Elasticsearch, ExplainParts};
+use http::StatusCode;
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+/// Maximum number of lines of a rendered score explanation
+const MAX_EXPLANATION_LINES: usize = 200;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ValidateQueryParams {
+    /// Name or pattern of the indices to validate the query against
+    index: String,
+
+    /// Query DSL query (the content of the `query` property of a search request)
+    query: Map<String, Value>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct ExplainDocumentParams {
+    /// Name of the index containing the document
+    index: String,
+
+    /// Id of the document
+    id: String,
+
+    /// Query DSL query (the content of the `query` property of a search request)
+    query: Map<String, Value>,
+}
+
+#[tool_router(router = query_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: validate query
+    #[tool(
+        description = "Validate a Query DSL query without running it. Returns the query as rewritten by \
+            Elasticsearch if it's valid, or readable errors if it's not.",
+        annotations(title = "Validate ES query", read_only_hint = true)
+    )]
+    async fn validate_query(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(ValidateQueryParams { index, query }): Parameters<ValidateQueryParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let validation = validate(&es_client, &index, query).await?;
+        if !validation.valid {
+            return Ok(validation.into_error());
+        }
+
+        let mut results = vec![Content::text("The query is valid.")];
+        let explanations = validation
+            .explanations
+            .iter()
+            .filter_map(|e| e.explanation.as_deref())
+            .collect::<Vec<_>>();
+        if let Some(explanation) = explanations.first() {
+            // Explanations are usually identical across indices
+            results.push(Content::text(format!("Rewritten query: {explanation}")));
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+
+    //---------------------------------------------------------------------------------------------
+    /// Tool: explain document
+    #[tool(
+        description = "Explain why a document matches a query or not, and how its score is computed.",
+        annotations(title = "Explain ES document score", read_only_hint = true)
+    )]
+    async fn explain_document(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(ExplainDocumentParams { index, id, query }): Parameters<ExplainDocumentParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let response = es_client
+            .explain(ExplainParts::IndexId(&index, &id))
+            .body(json!({ "query": query }))
+            .send()
+            .await;
+
+        let response: ExplainResponse = match response {
+            Ok(resp) if resp.status_code() == StatusCode::NOT_FOUND => {
+                audit::record_es_request(&resp);
+                let body = resp.text().await.map_err(internal_error)?;
+                if let Some(err) = not_found_error(&body) {
+                    return Err(err);
+                }
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Document [{id}] not found in index [{index}]."
+                ))]));
+            }
+            response => read_json(response).await?,
+        };
+
+        let status = if response.matched { "matches" } else { "does not match" };
+        let mut results = vec![Content::text(format!("Document [{id}] {status} the query."))];
+
+        if let Some(explanation) = &response.explanation {
+            let mut lines = Vec::new();
+            explanation.render(0, &mut lines);
+            let total = lines.len();
+            lines.truncate(MAX_EXPLANATION_LINES);
+            if total > lines.len() {
+                lines.push(format!("… [{} more lines]", total - lines.len()));
+            }
+            results.push(Content::text(lines.join("\n")));
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+}
+
+//-------------------------------------------------------------------------------------------------
+// Validation
+
+#[derive(Deserialize)]
+pub struct Validation {
+    pub valid: bool,
+    #[serde(default)]
+    explanations: Vec<ValidationExplanation>,
+    /// Error when explanations are not requested
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ValidationExplanation {
+    index: Option<String>,
+    #[serde(default)]
+    valid: bool,
+    explanation: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct QueryError {
+    error: String,
+    indices: Vec<String>,
+}
+
+/// Validate a query against some indices.
+pub async fn validate(
+    es_client: &Elasticsearch,
+    index: &str,
+    query: Map<String, Value>,
+) -> Result<Validation, rmcp::Error> {
+    let response = es_client
+        .indices()
+        .validate_query(IndicesValidateQueryParts::Index(&[index]))
+        .explain(true)
+        .body(json!({ "query": query }))
+        .send()
+        .await;
+    read_json(response).await
+}
+
+impl Validation {
+    /// Errors of an invalid query, deduplicated across indices.
+    fn errors(&self) -> Vec<QueryError> {
+        let mut errors: Vec<QueryError> = Vec::new();
+
+        let explanation_errors = self.explanations.iter().filter(|e| !e.valid).map(|e| {
+            let error = e.error.as_deref().unwrap_or("invalid query");
+            (readable_error(error), e.index.clone())
+        });
+        let other_error = self.error.as_deref().map(|e| (readable_error(e), None));
+
+        for (error, index) in explanation_errors.chain(other_error) {
+            let entry = match errors.iter_mut().find(|e| e.error == error) {
+                Some(entry) => entry,
+                None => {
+                    errors.push(QueryError {
+                        error,
+                        indices: Vec::new(),
+                    });
+                    errors.last_mut().unwrap()
+                }
+            };
+            if let Some(index) = index
+                && !entry.indices.contains(&index)
+            {
+                entry.indices.push(index);
+            }
+        }
+
+        errors
+    }
+
+    /// Tool result for an invalid query, that the LLM can use to fix it.
+    pub fn into_error(self) -> CallToolResult {
+        let errors = self.errors();
+        let mut content = vec![Content::text("The query is invalid:")];
+        match Content::json(json!({ "valid": false, "errors": errors })) {
+            Ok(json) => content.push(json),
+            Err(_) => content.extend(errors.into_iter().map(|e| Content::text(e.error))),
+        }
+        CallToolResult::error(content)
+    }
+}
+
+/// Make an Elasticsearch error message readable by removing Java exception class names, e.g.
+/// `org.elasticsearch.common.ParsingException: [match] unknown token` becomes `[match] unknown token`.
+fn readable_error(error: &str) -> String {
+    let is_class_name = |s: &str| {
+        !s.contains(char::is_whitespace) && s.contains('.') && (s.ends_with("Exception") || s.ends_with("Error"))
+    };
+
+    let mut parts: Vec<&str> = Vec::new();
+    for part in error.split(": ").map(str::trim) {
+        if !part.is_empty() && !is_class_name(part) && parts.last() != Some(&part) {
+            parts.push(part);
+        }
+    }
+    parts.join(": ")
+}
+
+//-------------------------------------------------------------------------------------------------
+// Explain
+
+/// The error of a 404 explain response, if it isn't a missing document: a missing document has no
+/// `error` object, unlike a missing index.
+fn not_found_error(body: &str) -> Option<rmcp::Error> {
+    let is_error = serde_json::from_str::<Value>(body).map_or(true, |body| body.get("error").is_some());
+    is_error.then(|| EsError::parse(StatusCode::NOT_FOUND.as_u16(), body).into_rmcp_error())
+}
+
+#[derive(Deserialize)]
+struct ExplainResponse {
+    #[serde(default)]
+    matched: bool,
+    explanation: Option<Explanation>,
+}
+
+#[derive(Deserialize)]
+struct Explanation {
+    value: f64,
+    description: String,
+    #[serde(default)]
+    details: Vec<Explanation>,
+}
+
+impl Explanation {
+    fn render(&self, depth: usize, lines: &mut Vec<String>) {
+        lines.push(format!("{}{} = {}", "  ".repeat(depth), self.value, self.description));
+        for detail in &self.details {
+            detail.render(depth + 1, lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::elasticsearch::errors;
+
+    #[test]
+    fn readable_errors() {
+        assert_eq!(
+            "[match] unknown token [START_ARRAY] after [query]",
+            readable_error(
+                "org.elasticsearch.common.ParsingException: [match] unknown token [START_ARRAY] after [query]"
+            )
+        );
+        assert_eq!(
+            "failed to create query: For input string: \"abc\"",
+            readable_error(
+                "org.elasticsearch.index.query.QueryShardException: failed to create query: \
+                java.lang.NumberFormatException: For input string: \"abc\""
+            )
+        );
+    }
+
+    #[test]
+    fn validation_errors() {
+        let validation: Validation = serde_json::from_value(json!({
+            "valid": false,
+            "explanations": [
+                {"index": "logs-1", "valid": false, "error": "java.lang.IllegalArgumentException: bad field"},
+                {"index": "logs-2", "valid": false, "error": "java.lang.IllegalArgumentException: bad field"},
+                {"index": "logs-3", "valid": true, "explanation": "+field:value"}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            vec![QueryError {
+                error: "bad field".to_string(),
+                indices: vec!["logs-1".to_string(), "logs-2".to_string()],
+            }],
+            validation.errors()
+        );
+    }
+
+    #[test]
+    fn explain_not_found() {
+        let missing_document = r#"{"_index": "logs", "_id": "42", "matched": false}"#;
+        assert!(not_found_error(missing_document).is_none());
+
+        let missing_index = r#"{
+            "error": {"type": "index_not_found_exception", "reason": "no such index [logz]", "index": "logz"},
+            "status": 404
+        }"#;
+        let err = not_found_error(missing_index).unwrap();
+        assert_eq!(Some("logz"), errors::missing_index(&err));
+    }
+}
@@ -0,0 +1,263 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Per-caller rate limiting (token bucket) and concurrency limits for tool calls.
+//!
+//! Global limits apply to all the tool calls of a caller, and per-tool limits apply in addition
+//! to the calls of a caller to a given tool.
+
+use crate::servers::elasticsearch::{Limits, LimitsConfig, RateLimitKey};
+use rmcp::model::ErrorCode;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// JSON-RPC error code for rate-limited requests, in the implementation-defined server error range.
+pub const RATE_LIMITED: ErrorCode = ErrorCode(-32029);
+
+/// Entries that are idle for this long are removed when the state grows too large.
+const IDLE_EXPIRY: Duration = Duration::from_secs(600);
+const MAX_IDLE_ENTRIES: usize = 10_000;
+
+/// Upper bound of the `retry_after_ms` hint, for very low rates
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(3600);
+
+/// Limit state key: caller and tool name (`None` for global limits)
+type Key = (String, Option<String>);
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    in_flight: usize,
+}
+
+pub struct RateLimiter {
+    config: LimitsConfig,
+    state: Arc<Mutex<HashMap<Key, Bucket>>>,
+}
+
+/// Keeps a tool call in-flight until dropped.
+pub struct Permit {
+    state: Arc<Mutex<HashMap<Key, Bucket>>>,
+    keys: Vec<Key>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        for key in &self.keys {
+            if let Some(bucket) = state.get_mut(key) {
+                bucket.in_flight = bucket.in_flight.saturating_sub(1);
+            }
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Create a rate limiter, failing if a limit of the configuration is invalid.
+    pub fn new(config: LimitsConfig) -> anyhow::Result<Self> {
+        config.default.check("limits")?;
+        for (tool, limits) in &config.tools {
+            limits.check(&format!("limits.tools.{tool}"))?;
+        }
+
+        Ok(RateLimiter {
+            config,
+            state: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// How callers are identified, to build the `caller` key of [`Self::acquire`].
+    pub fn key_by(&self) -> RateLimitKey {
+        self.config.key_by
+    }
+
+    /// Acquire a permit for a tool call. Fails with a [`RATE_LIMITED`] error if a limit is exceeded,
+    /// in which case no limit is consumed.
+    pub fn acquire(&self, caller: &str, tool: &str) -> Result<Permit, rmcp::Error> {
+        let mut limits: Vec<(Key, &Limits)> = vec![((caller.to_string(), None), &self.config.default)];
+        if let Some(tool_limits) = self.config.tools.get(tool) {
+            limits.push(((caller.to_string(), Some(tool.to_string())), tool_limits));
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.len() > MAX_IDLE_ENTRIES {
+            state.retain(|_, bucket| bucket.in_flight > 0 || now.duration_since(bucket.last_refill) < IDLE_EXPIRY);
+        }
+
+        // Check all limits before consuming any of them
+        for (key, limits) in &limits {
+            let bucket = state.entry(key.clone()).or_insert_with(|| Bucket {
+                tokens: limits.capacity(),
+                last_refill: now,
+                in_flight: 0,
+            });
+
+            if let Some(rate) = limits.rate {
+                let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * rate).min(limits.capacity());
+                bucket.last_refill = now;
+
+                if bucket.tokens < 1.0 {
+                    let retry_after = Duration::try_from_secs_f64((1.0 - bucket.tokens) / rate)
+                        .unwrap_or(MAX_RETRY_AFTER)
+                        .min(MAX_RETRY_AFTER);
+                    return Err(limited("rate", key, limits.capacity(), retry_after));
+                }
+            }
+
+            if let Some(max) = limits.max_in_flight
+                && bucket.in_flight >= max
+            {
+                // We can't know when in-flight calls will complete: suggest a short delay
+                return Err(limited("max_in_flight", key, max as f64, Duration::from_secs(1)));
+            }
+        }
+
+        for (key, limits) in &limits {
+            if let Some(bucket) = state.get_mut(key) {
+                if limits.rate.is_some() {
+                    bucket.tokens -= 1.0;
+                }
+                bucket.in_flight += 1;
+            }
+        }
+
+        Ok(Permit {
+            state: self.state.clone(),
+            keys: limits.into_iter().map(|(key, _)| key).collect(),
+        })
+    }
+}
+
+impl Limits {
+    fn check(&self, path: &str) -> anyhow::Result<()> {
+        if let Some(rate) = self.rate
+            && !(rate.is_finite() && rate > 0.0)
+        {
+            anyhow::bail!("{path}.rate must be a positive number of calls per second, got {rate}");
+        }
+        Ok(())
+    }
+
+    /// Bucket capacity: the burst size, or one second's worth of calls
+    fn capacity(&self) -> f64 {
+        match (self.burst, self.rate) {
+            (Some(burst), _) => burst as f64,
+            (None, Some(rate)) => rate.max(1.0),
+            (None, None) => 0.0,
+        }
+    }
+}
+
+fn limited(limit: &str, (caller, tool): &Key, value: f64, retry_after: Duration) -> rmcp::Error {
+    let retry_after_ms = retry_after.as_millis().max(1) as u64;
+    let scope = match tool {
+        Some(tool) => format!("tool '{tool}'"),
+        None => "all tools".to_string(),
+    };
+    rmcp::Error::new(
+        RATE_LIMITED,
+        format!("Too many requests ({limit} limit for {scope}), retry after {retry_after_ms} ms"),
+        Some(json!({
+            "limit": limit,
+            "value": value,
+            "caller": caller,
+            "tool": tool,
+            "retry_after_ms": retry_after_ms,
+        })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(rate: Option<f64>, burst: Option<u32>, max_in_flight: Option<usize>) -> Limits {
+        Limits {
+            rate,
+            burst,
+            max_in_flight,
+        }
+    }
+
+    #[test]
+    fn rate_limit() {
+        let limiter = RateLimiter::new(LimitsConfig {
+            default: limits(Some(0.001), Some(2), None),
+            tools: HashMap::new(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(limiter.acquire("a", "search").is_ok());
+        assert!(limiter.acquire("a", "search").is_ok());
+        let err = limiter.acquire("a", "search").err().unwrap();
+        assert_eq!(RATE_LIMITED, err.code);
+        assert!(err.data.unwrap()["retry_after_ms"].as_u64().unwrap() > 0);
+
+        // Other callers have their own bucket
+        assert!(limiter.acquire("b", "search").is_ok());
+    }
+
+    #[test]
+    fn concurrency_limit() {
+        let limiter = RateLimiter::new(LimitsConfig {
+            default: limits(None, None, None),
+            tools: HashMap::from([("search".to_string(), limits(None, None, Some(1)))]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let permit = limiter.acquire("a", "search").unwrap();
+        assert!(limiter.acquire("a", "search").is_err());
+        assert!(limiter.acquire("a", "esql").is_ok());
+
+        drop(permit);
+        assert!(limiter.acquire("a", "search").is_ok());
+    }
+
+    #[test]
+    fn invalid_rates() {
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let config = LimitsConfig {
+                default: limits(None, None, None),
+                tools: HashMap::from([("search".to_string(), limits(Some(rate), None, None))]),
+                ..Default::default()
+            };
+            assert!(RateLimiter::new(config).is_err());
+        }
+    }
+
+    #[test]
+    fn very_low_rate() {
+        let limiter = RateLimiter::new(LimitsConfig {
+            default: limits(Some(1e-300), Some(1), None),
+            tools: HashMap::new(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(limiter.acquire("a", "search").is_ok());
+        let err = limiter.acquire("a", "search").err().unwrap();
+        let retry_after_ms = err.data.unwrap()["retry_after_ms"].as_u64().unwrap();
+        assert_eq!(MAX_RETRY_AFTER.as_millis() as u64, retry_after_ms);
+    }
+}
@@ -0,0 +1,389 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Semantic search, so that LLMs don't have to build `semantic` and `knn` queries themselves.
+
+use crate::servers::elasticsearch::base_tools::{EsBaseTools, HitFormat, SearchResult};
+use crate::servers::elasticsearch::{discovery_tools, read_json};
+use elasticsearch::SearchParts;
+use rmcp::RoleServer;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, Content};
+use rmcp::service::RequestContext;
+use rmcp_macros::{tool, tool_router};
+use serde_json::{Map, Value, json};
+
+const DEFAULT_SIZE: u32 = 10;
+const MAX_SIZE: u32 = 100;
+
+/// Minimum number of results of each retriever that are combined with RRF
+const MIN_RANK_WINDOW_SIZE: u32 = 50;
+
+/// Minimum number of candidates considered by kNN searches on each shard
+const MIN_NUM_CANDIDATES: u32 = 100;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct SemanticSearchParams {
+    /// Name or pattern of the indices to search
+    index: String,
+
+    /// Natural language query
+    query: String,
+
+    /// `semantic_text` or `dense_vector` fields to search (optional, default: all `semantic_text` fields,
+    /// and `dense_vector` fields if `model_id` is provided)
+    fields: Option<Vec<String>>,
+
+    /// Id of the text embedding model used to index `dense_vector` fields, required to search them
+    model_id: Option<String>,
+
+    /// Combine with a full-text search using reciprocal rank fusion (RRF) (optional, default false)
+    hybrid: Option<bool>,
+
+    /// `text` fields of the full-text search of a hybrid search (optional, default: all `text` fields)
+    text_fields: Option<Vec<String>>,
+
+    /// Query DSL filter applied to the results (e.g. `{"term": {"language": "en"}}`)
+    filter: Option<Map<String, Value>>,
+
+    /// Number of results to return (optional, default: 10, max: 100)
+    size: Option<u32>,
+}
+
+#[tool_router(router = semantic_tool_router, vis = "pub(super)")]
+impl EsBaseTools {
+    //---------------------------------------------------------------------------------------------
+    /// Tool: semantic search
+    #[tool(
+        description = "Search with a natural language query, using the `semantic_text` and `dense_vector` fields \
+            found in the mappings, optionally combined with a full-text search. Returns scored hits with their \
+            `_id` and the most relevant passages as highlights.",
+        annotations(title = "Elasticsearch semantic search", read_only_hint = true)
+    )]
+    async fn semantic_search(
+        &self,
+        req_ctx: RequestContext<RoleServer>,
+        Parameters(SemanticSearchParams {
+            index,
+            query,
+            fields,
+            model_id,
+            hybrid,
+            text_fields,
+            filter,
+            size,
+        }): Parameters<SemanticSearchParams>,
+    ) -> Result<CallToolResult, rmcp::Error> {
+        let es_client = self.es_client.get(req_ctx);
+
+        let field_types = discovery_tools::field_types(&es_client, &index, &["*"]).await?;
+        let search_fields = match SearchFields::select(field_types, fields, model_id.is_some(), hybrid, text_fields) {
+            Ok(search_fields) => search_fields,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+
+        let size = size.unwrap_or(DEFAULT_SIZE).clamp(1, MAX_SIZE);
+        let body = search_fields.request(&query, model_id.as_deref(), filter, size);
+        let response = es_client.search(SearchParts::Index(&[&index])).body(body).send().await;
+        let response: SearchResult = read_json(response).await?;
+
+        let total = response.hits.total.as_ref().map_or(0, |t| t.value);
+        let mut results = vec![Content::text(format!(
+            "Found {total} results for '{query}' in {}:",
+            search_fields.describe()
+        ))];
+        self.push_hits_as("semantic_search", &response.hits, HitFormat::Metadata, &mut results)?;
+        if let Some(note) = search_fields.note {
+            results.push(Content::text(note));
+        }
+
+        Ok(CallToolResult::success(results))
+    }
+}
+
+/// The fields used by a semantic search.
+#[derive(Debug, Default, PartialEq)]
+struct SearchFields {
+    semantic: Vec<String>,
+    vectors: Vec<String>,
+    /// Fields of the full-text search, if hybrid
+    text: Option<Vec<String>>,
+    /// Why some fields weren't used
+    note: Option<String>,
+}
+
+impl SearchFields {
+    /// Select the fields to search from the fields of the indices and their type. Returns an error
+    /// message that the LLM can use to correct its request if there are no fields to search.
+    fn select(
+        field_types: Vec<(String, String)>,
+        requested: Option<Vec<String>>,
+        has_model: bool,
+        hybrid: Option<bool>,
+        text_fields: Option<Vec<String>>,
+    ) -> Result<SearchFields, String> {
+        // Skip the internal fields of `semantic_text` fields
+        let field_types = field_types
+            .into_iter()
+            .filter(|(name, _)| !name.starts_with('_') && !name.contains(".inference."))
+            .collect::<Vec<_>>();
+        let of_type = |type_: &str| {
+            field_types
+                .iter()
+                .filter(|(_, t)| t == type_)
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut fields = SearchFields::default();
+        match requested {
+            Some(requested) => {
+                for name in requested {
+                    match field_types.iter().find(|(n, _)| *n == name).map(|(_, t)| t.as_str()) {
+                        Some("semantic_text") => fields.semantic.push(name),
+                        Some("dense_vector") if has_model => fields.vectors.push(name),
+                        Some("dense_vector") => {
+                            return Err(format!(
+                                "Field [{name}] is a `dense_vector` field: provide the `model_id` of the text \
+                                embedding model used to index it."
+                            ));
+                        }
+                        Some(other) => {
+                            return Err(format!(
+                                "Field [{name}] has type [{other}]: only `semantic_text` and `dense_vector` fields \
+                                can be used for semantic search."
+                            ));
+                        }
+                        None => return Err(format!("Field [{name}] doesn't exist.")),
+                    }
+                }
+            }
+            None => {
+                fields.semantic = of_type("semantic_text");
+                let vectors = of_type("dense_vector");
+                if has_model {
+                    fields.vectors = vectors;
+                } else if !vectors.is_empty() {
+                    fields.note = Some(format!(
+                        "`dense_vector` fields [{}] were not searched: provide the `model_id` of the text \
+                        embedding model used to index them to search them.",
+                        vectors.join(", ")
+                    ));
+                }
+            }
+        }
+
+        if fields.semantic.is_empty() && fields.vectors.is_empty() {
+            let note = fields.note.map(|n| format!(" {n}")).unwrap_or_default();
+            return Err(format!(
+                "No `semantic_text` fields found to search.{note} Use the `search` tool with a full-text query instead."
+            ));
+        }
+
+        if hybrid.unwrap_or(false) {
+            fields.text = Some(text_fields.unwrap_or_else(|| of_type("text")));
+        }
+
+        Ok(fields)
+    }
+
+    fn describe(&self) -> String {
+        let mut all = self.semantic.iter().chain(&self.vectors).cloned().collect::<Vec<_>>();
+        if let Some(text) = &self.text {
+            all.push(format!(
+                "full-text on {}",
+                if text.is_empty() {
+                    "all fields".to_string()
+                } else {
+                    text.join(", ")
+                }
+            ));
+        }
+        all.join(", ")
+    }
+
+    /// Build the search request, with an RRF retriever if there are several retrievers.
+    fn request(&self, query: &str, model_id: Option<&str>, filter: Option<Map<String, Value>>, size: u32) -> Value {
+        let filter = filter.map(Value::Object);
+        let with_filter = |mut retriever: Map<String, Value>| {
+            if let Some(filter) = &filter {
+                retriever.insert("filter".to_string(), filter.clone());
+            }
+            retriever
+        };
+
+        let mut retrievers = Vec::new();
+        if !self.semantic.is_empty() {
+            let queries = self
+                .semantic
+                .iter()
+                .map(|field| json!({ "semantic": { "field": field, "query": query } }))
+                .collect::<Vec<_>>();
+            let query = match <[Value; 1]>::try_from(queries) {
+                Ok([query]) => query,
+                Err(queries) => json!({ "bool": { "should": queries } }),
+            };
+            retrievers.push(json!({ "standard": with_filter(json_object(json!({ "query": query }))) }));
+        }
+        for field in &self.vectors {
+            let knn = json_object(json!({
+                "field": field,
+                "k": size,
+                "num_candidates": size.saturating_mul(10).max(MIN_NUM_CANDIDATES),
+                "query_vector_builder": {
+                    "text_embedding": { "model_id": model_id, "model_text": query }
+                }
+            }));
+            retrievers.push(json!({ "knn": with_filter(knn) }));
+        }
+        if let Some(text_fields) = &self.text {
+            let mut multi_match = json_object(json!({ "query": query }));
+            if !text_fields.is_empty() {
+                multi_match.insert("fields".to_string(), json!(text_fields));
+            }
+            retrievers.push(json!({
+                "standard": with_filter(json_object(json!({ "query": { "multi_match": multi_match } })))
+            }));
+        }
+
+        let retriever = match <[Value; 1]>::try_from(retrievers) {
+            Ok([retriever]) => retriever,
+            Err(retrievers) => json!({
+                "rrf": {
+                    "retrievers": retrievers,
+                    "rank_window_size": size.max(MIN_RANK_WINDOW_SIZE)
+                }
+            }),
+        };
+
+        // Passages of semantic fields, and matches of full-text fields
+        let mut highlight = Map::new();
+        for field in &self.semantic {
+            highlight.insert(
+                field.clone(),
+                json!({ "type": "semantic", "number_of_fragments": 2, "order": "score" }),
+            );
+        }
+        for field in self.text.iter().flatten() {
+            highlight.insert(field.clone(), json!({}));
+        }
+
+        // Vectors are large and useless to the LLM, and semantic passages are in the highlights
+        let excludes = self.semantic.iter().chain(&self.vectors).collect::<Vec<_>>();
+
+        json!({
+            "size": size,
+            "retriever": retriever,
+            "highlight": { "fields": highlight },
+            "_source": { "excludes": excludes }
+        })
+    }
+}
+
+fn json_object(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_types() -> Vec<(String, String)> {
+        [
+            ("body", "semantic_text"),
+            ("body.inference.chunks.embeddings", "sparse_vector"),
+            ("embedding", "dense_vector"),
+            ("title", "text"),
+        ]
+        .into_iter()
+        .map(|(name, type_)| (name.to_string(), type_.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn select_fields() {
+        let fields = SearchFields::select(field_types(), None, false, Some(true), None).unwrap();
+        assert_eq!(vec!["body"], fields.semantic);
+        assert!(fields.vectors.is_empty());
+        assert_eq!(Some(vec!["title".to_string()]), fields.text);
+        assert!(fields.note.unwrap().contains("[embedding]"));
+
+        let fields = SearchFields::select(field_types(), None, true, None, None).unwrap();
+        assert_eq!(vec!["embedding"], fields.vectors);
+        assert_eq!(None, fields.text);
+
+        let error = SearchFields::select(field_types(), Some(vec!["title".to_string()]), false, None, None);
+        assert!(error.unwrap_err().contains("has type [text]"));
+    }
+
+    #[test]
+    fn semantic_request() {
+        let fields = SearchFields {
+            semantic: vec!["body".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            json!({
+                "size": 5,
+                "retriever": {"standard": {
+                    "query": {"semantic": {"field": "body", "query": "how to reset"}},
+                    "filter": {"term": {"lang": "en"}}
+                }},
+                "highlight": {"fields": {"body": {"type": "semantic", "number_of_fragments": 2, "order": "score"}}},
+                "_source": {"excludes": ["body"]}
+            }),
+            fields.request(
+                "how to reset",
+                None,
+                Some(json_object(json!({"term": {"lang": "en"}}))),
+                5
+            )
+        );
+    }
+
+    #[test]
+    fn hybrid_request() {
+        let fields = SearchFields {
+            vectors: vec!["embedding".to_string()],
+            text: Some(vec!["title".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            json!({
+                "size": 10,
+                "retriever": {"rrf": {
+                    "retrievers": [
+                        {"knn": {
+                            "field": "embedding",
+                            "k": 10,
+                            "num_candidates": 100,
+                            "query_vector_builder": {"text_embedding": {"model_id": "e5", "model_text": "reset"}}
+                        }},
+                        {"standard": {"query": {"multi_match": {"query": "reset", "fields": ["title"]}}}}
+                    ],
+                    "rank_window_size": 50
+                }},
+                "highlight": {"fields": {"title": {}}},
+                "_source": {"excludes": ["embedding"]}
+            }),
+            fields.request("reset", Some("e5"), None, 10)
+        );
+    }
+}
@@ -0,0 +1,108 @@
+// Licensed to Elasticsearch B.V. under one or more contributor
+// license agreements. See the NOTICE file distributed with
+// this work for additional information regarding copyright
+// ownership. Elasticsearch B.V. licenses this file to you under
+// the Apache License, Version 2.0 (the "License"); you may
+// not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Minimal JSON diff and merge, used to preview changes before they're applied to a cluster.
+
+use serde_json::Value;
+
+/// Deep-merge `patch` into `base`. Objects are merged recursively, any other value replaces
+/// the existing one. This mimics how Elasticsearch merges mapping and settings updates.
+pub fn merge(base: &mut Value, patch: &Value) {
+    match (base, patch) {
+        (Value::Object(base), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match base.get_mut(key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, patch) => *base = patch.clone(),
+    }
+}
+
+/// Compute the differences between two JSON values, as a list of human-readable lines:
+/// - `+ path: value` for added values
+/// - `- path: value` for removed values
+/// - `~ path: old -> new` for changed values
+///
+/// Objects are compared recursively, arrays and scalars are compared as a whole.
+pub fn diff(old: &Value, new: &Value) -> Vec<String> {
+    let mut result = Vec::new();
+    diff_at("", old, new, &mut result);
+    result
+}
+
+fn diff_at(path: &str, old: &Value, new: &Value, result: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let path = join(path, key);
+                match new.get(key) {
+                    Some(new_value) => diff_at(&path, old_value, new_value, result),
+                    None => result.push(format!("- {path}: {old_value}")),
+                }
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    result.push(format!("+ {}: {new_value}", join(path, key)));
+                }
+            }
+        }
+        (old, new) if old != new => {
+            let path = if path.is_empty() { "." } else { path };
+            result.push(format!("~ {path}: {old} -> {new}"));
+        }
+        _ => {}
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_objects() {
+        let mut base = json!({"a": 1, "b": {"c": 2, "d": 3}});
+        merge(&mut base, &json!({"b": {"c": 4, "e": [5]}, "f": "g"}));
+        assert_eq!(base, json!({"a": 1, "b": {"c": 4, "d": 3, "e": [5]}, "f": "g"}));
+    }
+
+    #[test]
+    fn diff_values() {
+        let old = json!({"a": 1, "b": {"c": 2, "d": 3}, "x": [1]});
+        let new = json!({"a": 1, "b": {"c": 4}, "x": [1, 2], "y": true});
+
+        assert_eq!(
+            diff(&old, &new),
+            vec!["~ b.c: 2 -> 4", "- b.d: 3", "~ x: [1] -> [1,2]", "+ y: true"]
+        );
+        assert!(diff(&old, &old).is_empty());
+        assert_eq!(diff(&json!(1), &json!(2)), vec!["~ .: 1 -> 2"]);
+    }
+}
@@ -18,6 +18,7 @@
 use serde::{Deserialize, Deserializer};
 
 pub mod interpolator;
+pub mod json_diff;
 pub mod rmcp_ext;
 
 /// Deserialize a string, and return `None` if it's empty. Useful for configuration fields like
@@ -29,3 +30,37 @@ pub fn none_if_empty_string<'de, D: Deserializer<'de>>(deserializer: D) -> Resul
         _ => Ok(s),
     }
 }
+
+/// Levenshtein edit distance between two strings, counted in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(current)
+            };
+            prev = current;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(0, edit_distance("logs", "logs"));
+        assert_eq!(1, edit_distance("logz", "logs"));
+        assert_eq!(1, edit_distance("log-", "logs-"));
+        assert_eq!(3, edit_distance("", "abc"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+    }
+}